@@ -0,0 +1,590 @@
+use crate::{
+    dependencies, journal,
+    recurrence::Recurrence,
+    storage,
+    tabs::{
+        Duration, Options, Priority, Task, TaskCommandError, TaskList, TaskStatus, assign_task_ids,
+        complete_recurring, disp_md, extract_tags, find_task, next_task_id, parse_date,
+    },
+};
+use chrono::NaiveDate;
+use std::{collections::HashMap, path::PathBuf, process::ExitCode};
+
+/*
+    Non-interactive interface used for scripting. Every subcommand loads the
+    same json store as the tui, applies a single change and writes it back
+    without ever touching the terminal mode.
+*/
+
+const USAGE: &str = "\
+usage: todoapp [--file <path>] [--data-dir <dir>] [<command>]
+
+Runs the interactive interface when no command is given.
+
+Tasks are stored in list.json inside the data directory, which is
+$CLTODO_DATA_DIR, $XDG_DATA_HOME/cltodo or ~/.local/share/cltodo
+unless --data-dir is given. --file reads and writes a single file instead.
+With CLTODO_STORAGE=sqlite the data file is list.db, an sqlite database,
+and so is any --file ending in .db, .sqlite or .sqlite3.
+
+commands:
+  add <title> [--notes <notes>] [--list <list>] [--date <date>] [--status <status>] [--priority <priority>] [--tags <a,b>] [--repeat <rule>] [--duration <dd:hh:mm>] [--parent <id>]
+  list [--list <list>] [--status <open|all|status>] [--tag <tag>]
+  done <id>
+  block <id> <blocker-id>
+  unblock <id> [<blocker-id>]
+  changes [--since <date>]
+  edit <id> [--title <title>] [--notes <notes>] [--date <date>] [--status <status>] [--priority <priority>] [--tags <a,b>] [--repeat <rule>] [--duration <dd:hh:mm>]
+  help [<command>]
+
+<id> is the task id printed by add and list.
+<date> accepts +d, -d, mmdd or yyyymmdd.
+<status> is one of not-started, in-progress, finished.
+<priority> is one of none, low, medium, high, critical.
+<rule> is <daily|weekly|monthly|yearly>[/<interval>] [<weekdays|day>] [until <date>],
+e.g. 'weekly/2 mon,thu' or 'monthly 1 until 20301231', and 'none' to stop repeating.
+
+A task cannot be finished while a task blocking it is still open.
+
+Every saved change is also appended to <file>.journal. changes lists the ones
+made since the start of <date>, yesterday by default.
+
+Every save of a json file keeps the previous file as <file>.<timestamp>.bak, up to 5 of them
+unless the interface was set to keep a different number.
+
+Commands that change tasks take <file>.lock as the interface does, and fail while the
+interface or another command has the data file open.
+
+exit codes: 2 invalid argument, 3 not found, 4 invalid or newer file format, 5 i/o error,
+6 data file in use";
+
+pub enum StatusFilter {
+    All,
+    Open,
+    Only(TaskStatus),
+}
+
+impl StatusFilter {
+    fn matches(&self, status: &TaskStatus) -> bool {
+        match self {
+            StatusFilter::All => true,
+            StatusFilter::Open => {
+                *status == TaskStatus::NotStarted || *status == TaskStatus::InProgress
+            }
+            StatusFilter::Only(s) => s == status,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TaskFields {
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    pub status: Option<TaskStatus>,
+    pub priority: Option<Priority>,
+    pub tags: Option<Vec<String>>,
+    pub recurrence: Option<Option<Recurrence>>,
+    pub date: Option<NaiveDate>,
+    pub duration: Option<Duration>,
+}
+
+impl TaskFields {
+    fn apply(self, task: &mut Task) {
+        if let Some(title) = self.title {
+            let (title, tags) = extract_tags(&title);
+            task.title = title;
+            for tag in tags {
+                task.add_tag(&tag);
+            }
+        }
+        if let Some(tags) = self.tags {
+            task.tags.clear();
+            for tag in tags {
+                task.add_tag(&tag);
+            }
+        }
+        if let Some(notes) = self.notes {
+            task.notes = notes;
+        }
+        if let Some(status) = self.status {
+            task.status = status;
+        }
+        if let Some(priority) = self.priority {
+            task.priority = priority;
+        }
+        if let Some(mut recurrence) = self.recurrence {
+            if let Some(rule) = recurrence.as_mut() {
+                rule.keep_anchor(task.recurrence.as_ref());
+            }
+            task.recurrence = recurrence;
+        }
+        if let Some(date) = self.date {
+            task.date = date;
+        }
+        if let Some(duration) = self.duration {
+            task.duration = duration;
+        }
+    }
+}
+
+pub enum Command {
+    Add {
+        list: Option<String>,
+        parent: Option<u64>,
+        fields: TaskFields,
+    },
+    List {
+        list: Option<String>,
+        status: StatusFilter,
+        tag: Option<String>,
+    },
+    Done {
+        task: u64,
+    },
+    Edit {
+        task: u64,
+        fields: TaskFields,
+    },
+    Block {
+        task: u64,
+        blocker: u64,
+    },
+    // no blocker removes all of them
+    Unblock {
+        task: u64,
+        blocker: Option<u64>,
+    },
+    Changes {
+        since: NaiveDate,
+    },
+    // the usage of a single command when one is given
+    Help {
+        command: Option<String>,
+    },
+}
+
+pub struct Args {
+    pub data_file: String,
+    pub data_dir: PathBuf,
+    pub command: Option<Command>,
+}
+
+// collects "--flag value" pairs so each subcommand can take the ones it
+// understands and reject the rest
+#[derive(Default)]
+struct Flags {
+    values: Vec<(String, String)>,
+}
+
+impl Flags {
+    fn take(&mut self, name: &str) -> Option<String> {
+        let index = self.values.iter().position(|(flag, _)| flag == name)?;
+        Some(self.values.remove(index).1)
+    }
+
+    fn take_fields(&mut self) -> Result<TaskFields, TaskCommandError> {
+        Ok(TaskFields {
+            title: self.take("title").or_else(|| self.take("name")),
+            notes: self.take("notes"),
+            status: self.take("status").map(|s| parse_status(&s)).transpose()?,
+            priority: self
+                .take("priority")
+                .map(|p| Priority::from_name(&p).ok_or(TaskCommandError::InvalidOption))
+                .transpose()?,
+            tags: self
+                .take("tags")
+                .map(|t| t.split(',').map(String::from).collect()),
+            recurrence: match self.take("repeat").as_deref() {
+                None => None,
+                Some("none") => Some(None),
+                Some(rule) => Some(Some(rule.parse()?)),
+            },
+            date: self
+                .take("date")
+                .map(|d| parse_date(&d).ok_or(TaskCommandError::InvalidOption))
+                .transpose()?,
+            duration: self.take("duration").map(|d| d.parse()).transpose()?,
+        })
+    }
+
+    fn finish(self) -> Result<(), TaskCommandError> {
+        if self.values.is_empty() {
+            Ok(())
+        } else {
+            Err(TaskCommandError::InvalidOption)
+        }
+    }
+}
+
+fn parse_status(name: &str) -> Result<TaskStatus, TaskCommandError> {
+    TaskStatus::from_name(name).ok_or(TaskCommandError::InvalidOption)
+}
+
+fn parse_id(text: &str) -> Result<u64, TaskCommandError> {
+    text.parse::<u64>().or(Err(TaskCommandError::NotANumber))
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, TaskCommandError> {
+        let mut data_file = None;
+        let mut data_dir = None;
+        let mut positional: Vec<String> = Vec::new();
+        let mut flags = Flags::default();
+        let mut help = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => help = true,
+                "-f" | "--file" => {
+                    data_file = Some(args.next().ok_or(TaskCommandError::MissingField)?)
+                }
+                "-d" | "--data-dir" => {
+                    data_dir = Some(args.next().ok_or(TaskCommandError::MissingField)?)
+                }
+                flag if flag.starts_with("--") => {
+                    let value = args.next().ok_or(TaskCommandError::MissingField)?;
+                    flags.values.push((flag[2..].to_string(), value));
+                }
+                _ => positional.push(arg),
+            }
+        }
+
+        if positional.first().is_some_and(|command| command == "help") {
+            positional.remove(0);
+            help = true;
+        }
+        // help is shown for whatever else was given
+        let mut positional = positional.into_iter();
+        let command = match positional.next().as_deref() {
+            command if help => Some(Command::Help {
+                command: command.map(str::to_string),
+            }),
+            None => None,
+            Some("add") => Some(Command::Add {
+                list: flags.take("list"),
+                parent: flags.take("parent").map(|p| parse_id(&p)).transpose()?,
+                fields: TaskFields {
+                    title: Some(positional.next().ok_or(TaskCommandError::MissingField)?),
+                    ..flags.take_fields()?
+                },
+            }),
+            Some("list" | "ls") => Some(Command::List {
+                list: flags.take("list"),
+                tag: flags.take("tag"),
+                status: match flags.take("status").as_deref() {
+                    None | Some("all") => StatusFilter::All,
+                    Some("open") => StatusFilter::Open,
+                    Some(s) => StatusFilter::Only(parse_status(s)?),
+                },
+            }),
+            Some("done") => Some(Command::Done {
+                task: parse_id(&positional.next().ok_or(TaskCommandError::MissingField)?)?,
+            }),
+            Some("edit") => Some(Command::Edit {
+                task: parse_id(&positional.next().ok_or(TaskCommandError::MissingField)?)?,
+                fields: flags.take_fields()?,
+            }),
+            Some("block") => Some(Command::Block {
+                task: parse_id(&positional.next().ok_or(TaskCommandError::MissingField)?)?,
+                blocker: parse_id(&positional.next().ok_or(TaskCommandError::MissingField)?)?,
+            }),
+            Some("unblock") => Some(Command::Unblock {
+                task: parse_id(&positional.next().ok_or(TaskCommandError::MissingField)?)?,
+                blocker: positional.next().map(|b| parse_id(&b)).transpose()?,
+            }),
+            Some("changes") => Some(Command::Changes {
+                since: parse_date(&flags.take("since").unwrap_or("-1".to_string()))
+                    .ok_or(TaskCommandError::InvalidOption)?,
+            }),
+            Some(_) => return Err(TaskCommandError::UnknownCommand),
+        };
+
+        if !help {
+            if positional.next().is_some() {
+                return Err(TaskCommandError::InvalidOption);
+            }
+            flags.finish()?;
+        }
+
+        let data_dir = storage::data_dir(data_dir.as_deref());
+        let data_file = data_file.unwrap_or_else(|| {
+            data_dir
+                .join(storage::default_data_file())
+                .to_string_lossy()
+                .into_owned()
+        });
+        Ok(Args {
+            data_file,
+            data_dir,
+            command,
+        })
+    }
+}
+
+fn find_list<'a>(
+    task_lists: &'a mut [TaskList],
+    name: Option<&str>,
+) -> Result<&'a mut TaskList, TaskCommandError> {
+    match name {
+        Some(name) => task_lists.iter_mut().find(|list| list.name == name),
+        None => task_lists.first_mut(),
+    }
+    .ok_or(TaskCommandError::NotFound)
+}
+
+fn find_task_mut(task_lists: &mut [TaskList], id: u64) -> Result<&mut Task, TaskCommandError> {
+    let (list_index, path) = find_task(task_lists, id).ok_or(TaskCommandError::NotFound)?;
+    task_lists[list_index]
+        .get_task_mut(&path)
+        .ok_or(TaskCommandError::NotFound)
+}
+
+fn find_siblings_mut(
+    task_lists: &mut [TaskList],
+    id: u64,
+) -> Result<(&mut Vec<Task>, usize), TaskCommandError> {
+    let (list_index, path) = find_task(task_lists, id).ok_or(TaskCommandError::NotFound)?;
+    task_lists[list_index]
+        .siblings_mut(&path)
+        .ok_or(TaskCommandError::NotFound)
+}
+
+// applies a change to a task and creates the next occurrence when it
+// finished a recurring one, returning the id of that occurrence. Finishing
+// a task that is still blocked is refused.
+fn update_task(
+    task_lists: &mut [TaskList],
+    id: u64,
+    change: impl FnOnce(&mut Task),
+) -> Result<Option<u64>, TaskCommandError> {
+    let task_index = dependencies::task_index(task_lists);
+    let blocked = task_index
+        .get(&id)
+        .is_some_and(|task| dependencies::is_blocked(&task_index, task));
+
+    let (siblings, index) = find_siblings_mut(task_lists, id)?;
+    let was_finished = siblings[index].status == TaskStatus::Finished;
+    change(&mut siblings[index]);
+    if blocked && !was_finished && siblings[index].status == TaskStatus::Finished {
+        return Err(TaskCommandError::Blocked);
+    }
+    let len = siblings.len();
+    complete_recurring(siblings, index);
+    Ok((siblings.len() > len).then(|| siblings[index + 1].id))
+}
+
+// the lists and the settings, which are written back untouched, of a
+// store that holds no lists while it does not exist yet
+fn load(data_file: &str) -> Result<(Vec<TaskList>, Options), TaskCommandError> {
+    if !storage::data_exists(data_file) {
+        return Ok((Vec::new(), Options::default()));
+    }
+    let mut file = storage::load_data(data_file)?;
+    assign_task_ids(&mut file.lists);
+    Ok((file.lists, file.settings))
+}
+
+// a store read to be changed, saving writes only what changed since
+// it holds the lock on the data file throughout, as the tui does
+struct Store<'a> {
+    _lock: storage::DataLock,
+    data_file: &'a str,
+    base: Vec<TaskList>,
+    lists: Vec<TaskList>,
+    settings: Options,
+}
+
+impl<'a> Store<'a> {
+    fn open(data_file: &'a str) -> Result<Self, TaskCommandError> {
+        let lock = storage::lock_data(data_file)?.ok_or(TaskCommandError::ReadOnly)?;
+        let (lists, settings) = load(data_file)?;
+        Ok(Store {
+            _lock: lock,
+            data_file,
+            base: lists.clone(),
+            lists,
+            settings,
+        })
+    }
+
+    fn save(&self) -> Result<(), TaskCommandError> {
+        storage::save_changes(self.data_file, &self.base, &self.lists, &self.settings)?;
+        journal::Journal::new(self.data_file).record(&self.lists)
+    }
+}
+
+fn print_tasks(
+    tasks: &[Task],
+    depth: usize,
+    task_index: &HashMap<u64, &Task>,
+    shown: &dyn Fn(&Task) -> bool,
+) {
+    for task in tasks {
+        if shown(task) {
+            println!(
+                "{:>6} {:indent$}[{}] {:<4} {:<40} {} {}{}{}{}",
+                task.id,
+                "",
+                task.status.get_symbol(),
+                task.priority.get_symbol(),
+                task.title,
+                disp_md(task.date),
+                task.duration,
+                if task.recurrence.is_some() {
+                    " ↻"
+                } else {
+                    ""
+                },
+                if dependencies::is_blocked(task_index, task) {
+                    " ⊘"
+                } else {
+                    ""
+                },
+                task.tags
+                    .iter()
+                    .map(|tag| format!(" #{tag}"))
+                    .collect::<String>(),
+                indent = depth * 2,
+            );
+        }
+        print_tasks(&task.sub_tasks, depth + 1, task_index, shown);
+    }
+}
+
+// the line of USAGE describing one command
+fn command_usage(command: &str) -> Result<String, TaskCommandError> {
+    let command = if command == "ls" { "list" } else { command };
+    USAGE
+        .lines()
+        .find(|line| {
+            line.strip_prefix("  ")
+                .and_then(|line| line.strip_prefix(command))
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+        })
+        .map(|line| {
+            format!(
+                "usage: todoapp [--file <path>] [--data-dir <dir>] {}",
+                line.trim()
+            )
+        })
+        .ok_or(TaskCommandError::UnknownCommand)
+}
+
+fn execute(command: Command, data_file: &str) -> Result<(), TaskCommandError> {
+    match command {
+        Command::Help { command: None } => println!("{USAGE}"),
+        Command::Help {
+            command: Some(command),
+        } => println!("{}", command_usage(&command)?),
+        Command::List { list, status, tag } => {
+            let (task_lists, _) = load(data_file)?;
+            let task_index = dependencies::task_index(&task_lists);
+            let shown: Vec<&TaskList> = match list {
+                Some(name) => vec![
+                    task_lists
+                        .iter()
+                        .find(|l| l.name == name)
+                        .ok_or(TaskCommandError::NotFound)?,
+                ],
+                None => task_lists.iter().collect(),
+            };
+            for task_list in shown {
+                println!("{}", task_list.name);
+                print_tasks(&task_list.tasks, 0, &task_index, &|task| {
+                    status.matches(&task.status) && tag.as_ref().is_none_or(|t| task.has_tag(t))
+                });
+            }
+        }
+        Command::Add {
+            list,
+            parent,
+            fields,
+        } => {
+            let mut store = Store::open(data_file)?;
+            let task_lists = &mut store.lists;
+            let list_name = list.unwrap_or_else(|| {
+                task_lists
+                    .first()
+                    .map_or("Tasks".to_string(), |l| l.name.clone())
+            });
+            if !task_lists.iter().any(|l| l.name == list_name) {
+                task_lists.push(TaskList::new(list_name.clone(), None));
+            }
+
+            let mut task = Task {
+                id: next_task_id(),
+                date: chrono::offset::Local::now().date_naive(),
+                ..Default::default()
+            };
+            fields.apply(&mut task);
+            let id = task.id;
+
+            // sub-tasks live with their parent, whichever list that is in
+            let siblings = match parent {
+                Some(parent) => &mut find_task_mut(task_lists, parent)?.sub_tasks,
+                None => &mut find_list(task_lists, Some(&list_name))?.tasks,
+            };
+            siblings.push(task);
+            // a task added as finished starts its next occurrence right away
+            let index = siblings.len() - 1;
+            complete_recurring(siblings, index);
+            let next = siblings.get(index + 1).map(|t| t.id);
+
+            store.save()?;
+            println!("{id}");
+            if let Some(next) = next {
+                println!("{next}");
+            }
+        }
+        Command::Done { task } => {
+            let mut store = Store::open(data_file)?;
+            let task_lists = &mut store.lists;
+            let next = update_task(task_lists, task, |t| t.status = TaskStatus::Finished)?;
+            store.save()?;
+            if let Some(next) = next {
+                println!("{next}");
+            }
+        }
+        Command::Edit { task, fields } => {
+            let mut store = Store::open(data_file)?;
+            let task_lists = &mut store.lists;
+            let next = update_task(task_lists, task, |t| fields.apply(t))?;
+            store.save()?;
+            if let Some(next) = next {
+                println!("{next}");
+            }
+        }
+        Command::Block { task, blocker } => {
+            let mut store = Store::open(data_file)?;
+            let task_lists = &mut store.lists;
+            dependencies::add_blocker(task_lists, task, blocker)?;
+            store.save()?;
+        }
+        Command::Unblock { task, blocker } => {
+            let mut store = Store::open(data_file)?;
+            let task_lists = &mut store.lists;
+            dependencies::remove_blocker(task_lists, task, blocker)?;
+            store.save()?;
+        }
+        Command::Changes { since } => {
+            let entries = journal::read(&journal::journal_path(data_file))?;
+            let since = journal::start_of_day(since).ok_or(TaskCommandError::InvalidOption)?;
+            for line in journal::changes_since(&entries, since) {
+                println!("{line}");
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn run(command: Command, data_file: &str) -> ExitCode {
+    match execute(command, data_file) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => report(e),
+    }
+}
+
+pub fn report(error: TaskCommandError) -> ExitCode {
+    eprintln!("todoapp: {error}");
+    ExitCode::from(error.exit_code())
+}
@@ -0,0 +1,113 @@
+use crate::tabs::{Task, TaskCommandError, TaskList, TaskStatus, find_task};
+use std::collections::HashMap;
+
+/*
+    Blocked-by relations between tasks. Edges are stored on the blocked
+    task as the ids of its blockers and may point into any list. Ids that
+    no longer exist (deleted blockers) are ignored.
+*/
+
+// every task in every list by id
+pub fn task_index(task_lists: &[TaskList]) -> HashMap<u64, &Task> {
+    fn collect<'a>(tasks: &'a [Task], index: &mut HashMap<u64, &'a Task>) {
+        for task in tasks {
+            index.insert(task.id, task);
+            collect(&task.sub_tasks, index);
+        }
+    }
+
+    let mut index = HashMap::new();
+    for list in task_lists {
+        collect(&list.tasks, &mut index);
+    }
+    index
+}
+
+fn is_done(task: &Task) -> bool {
+    task.status == TaskStatus::Finished || task.status == TaskStatus::Deleted
+}
+
+// the blockers of task that still have to be finished
+pub fn open_blockers<'a>(index: &HashMap<u64, &'a Task>, task: &Task) -> Vec<&'a Task> {
+    task.blocked_by
+        .iter()
+        .filter_map(|id| index.get(id).copied())
+        .filter(|blocker| !is_done(blocker))
+        .collect()
+}
+
+pub fn is_blocked(index: &HashMap<u64, &Task>, task: &Task) -> bool {
+    !open_blockers(index, task).is_empty()
+}
+
+// tasks that list id as one of their blockers
+pub fn dependents<'a>(index: &HashMap<u64, &'a Task>, id: u64) -> Vec<&'a Task> {
+    let mut tasks: Vec<&Task> = index
+        .values()
+        .filter(|task| task.blocked_by.contains(&id))
+        .copied()
+        .collect();
+    tasks.sort_by_key(|task| task.id);
+    tasks
+}
+
+// true when blocker already depends on task, directly or through others
+fn reaches(index: &HashMap<u64, &Task>, from: u64, target: u64) -> bool {
+    let mut stack = vec![from];
+    let mut visited = Vec::new();
+    while let Some(id) = stack.pop() {
+        if id == target {
+            return true;
+        }
+        if visited.contains(&id) {
+            continue;
+        }
+        visited.push(id);
+        if let Some(task) = index.get(&id) {
+            stack.extend(task.blocked_by.iter().copied());
+        }
+    }
+    false
+}
+
+// records that task cannot be finished before blocker, refusing cycles
+pub fn add_blocker(
+    task_lists: &mut [TaskList],
+    task: u64,
+    blocker: u64,
+) -> Result<(), TaskCommandError> {
+    let index = task_index(task_lists);
+    if !index.contains_key(&task) || !index.contains_key(&blocker) {
+        return Err(TaskCommandError::NotFound);
+    }
+    if reaches(&index, blocker, task) {
+        return Err(TaskCommandError::DependencyCycle);
+    }
+
+    let (list, path) = find_task(task_lists, task).ok_or(TaskCommandError::NotFound)?;
+    let task = task_lists[list]
+        .get_task_mut(&path)
+        .ok_or(TaskCommandError::NotFound)?;
+    if !task.blocked_by.contains(&blocker) {
+        task.blocked_by.push(blocker);
+    }
+    Ok(())
+}
+
+// removes one blocker from task, or all of them when blocker is None
+pub fn remove_blocker(
+    task_lists: &mut [TaskList],
+    task: u64,
+    blocker: Option<u64>,
+) -> Result<(), TaskCommandError> {
+    let (list, path) = find_task(task_lists, task).ok_or(TaskCommandError::NotFound)?;
+    let task = task_lists[list]
+        .get_task_mut(&path)
+        .ok_or(TaskCommandError::NotFound)?;
+    match blocker {
+        Some(id) if !task.blocked_by.contains(&id) => return Err(TaskCommandError::NotFound),
+        Some(id) => task.blocked_by.retain(|b| *b != id),
+        None => task.blocked_by.clear(),
+    }
+    Ok(())
+}
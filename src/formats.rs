@@ -0,0 +1,219 @@
+use crate::{
+    storage,
+    tabs::{Task, TaskCommandError, TaskList, assign_task_ids},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+/*
+    Task files of other programs. Every format works on the text of the
+    file: import turns it into task lists, export writes lists back out.
+*/
+pub mod csv;
+pub mod icalendar;
+pub mod markdown;
+pub mod org;
+pub mod taskwarrior;
+pub mod todotxt;
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    TodoTxt,
+    Markdown,
+    ICalendar,
+    Csv,
+    Org,
+    // import only
+    Taskwarrior,
+}
+
+pub struct Imported {
+    pub lists: Vec<TaskList>,
+    // shown after importing when not empty, e.g. what could not be brought over
+    pub summary: Vec<String>,
+}
+
+impl Format {
+    // as used in the import-<format> and export-<format> commands
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "todotxt" => Some(Format::TodoTxt),
+            "md" => Some(Format::Markdown),
+            "ics" => Some(Format::ICalendar),
+            "csv" => Some(Format::Csv),
+            "org" => Some(Format::Org),
+            "taskwarrior" => Some(Format::Taskwarrior),
+            _ => None,
+        }
+    }
+
+    pub fn import(self, text: &str, default_list: &str) -> Result<Imported, TaskCommandError> {
+        let lists = match self {
+            Format::TodoTxt => todotxt::import(text, default_list),
+            Format::Markdown => markdown::import(text, default_list),
+            Format::ICalendar => icalendar::import(text, default_list),
+            Format::Csv => csv::import(text, default_list)?,
+            Format::Org => org::import(text, default_list),
+            Format::Taskwarrior => return taskwarrior::import(text, default_list),
+        };
+        Ok(Imported {
+            lists,
+            summary: Vec::new(),
+        })
+    }
+
+    pub fn export(self, task_lists: &[&TaskList]) -> Result<String, TaskCommandError> {
+        match self {
+            Format::TodoTxt => Ok(todotxt::export(task_lists)),
+            Format::Markdown => Ok(markdown::export(task_lists)),
+            Format::ICalendar => Ok(icalendar::export(task_lists)),
+            Format::Csv => Ok(csv::export(task_lists)),
+            Format::Org => Ok(org::export(task_lists)),
+            Format::Taskwarrior => Err(TaskCommandError::UnknownCommand),
+        }
+    }
+}
+
+pub fn read(filename: &str) -> Result<String, TaskCommandError> {
+    fs::read_to_string(filename).map_err(storage::io_error)
+}
+
+pub fn write(filename: &str, text: &str) -> Result<(), TaskCommandError> {
+    storage::write_file(filename, text.as_bytes())
+}
+
+// the list for tasks a file does not place anywhere, named after the file
+pub fn default_list_name(filename: &str) -> String {
+    Path::new(filename)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or_else(|| "Imported".to_string())
+}
+
+// "all", a list number or a list name
+pub fn pick_lists<'a>(
+    task_lists: &'a [TaskList],
+    which: &str,
+) -> Result<Vec<&'a TaskList>, TaskCommandError> {
+    if which == "all" {
+        return Ok(task_lists.iter().collect());
+    }
+    let list = match which.parse::<usize>() {
+        Ok(index) => task_lists.get(index),
+        Err(_) => task_lists.iter().find(|list| list.name == which),
+    };
+    list.map(|list| vec![list])
+        .ok_or(TaskCommandError::NotFound)
+}
+
+// imported tasks join the list of the same name, if there is one
+pub fn add_lists(task_lists: &mut Vec<TaskList>, imported: Vec<TaskList>) {
+    for list in imported {
+        match task_lists.iter_mut().find(|l| l.name == list.name) {
+            Some(existing) => existing.tasks.extend(list.tasks),
+            None => task_lists.push(list),
+        }
+    }
+    assign_task_ids(task_lists);
+}
+
+// a task with its own key and the key of its parent
+pub type TreeEntry = (Option<String>, Option<String>, Task);
+
+/*
+    Turns entries into a tree, keeping their order. Tasks with a missing
+    parent stay at the top level.
+*/
+pub fn build_tree(tasks: Vec<TreeEntry>) -> Vec<Task> {
+    let keys: HashSet<String> = tasks.iter().filter_map(|(key, _, _)| key.clone()).collect();
+    let mut children: HashMap<String, Vec<(Option<String>, Task)>> = HashMap::new();
+    let mut top_level = Vec::new();
+    for (key, parent, task) in tasks {
+        match parent.filter(|parent| keys.contains(parent) && Some(parent) != key.as_ref()) {
+            Some(parent) => children.entry(parent).or_default().push((key, task)),
+            None => top_level.push((key, task)),
+        }
+    }
+
+    fn attach(
+        key: Option<String>,
+        mut task: Task,
+        children: &mut HashMap<String, Vec<(Option<String>, Task)>>,
+    ) -> Task {
+        if let Some(key) = key
+            && let Some(found) = children.remove(&key)
+        {
+            for (key, child) in found {
+                task.sub_tasks.push(attach(key, child, children));
+            }
+        }
+        task
+    }
+
+    let mut tasks: Vec<Task> = top_level
+        .into_iter()
+        .map(|(key, task)| attach(key, task, &mut children))
+        .collect();
+    // tasks whose parents point at each other have no way to the top
+    for (_, found) in children.drain() {
+        tasks.extend(found.into_iter().map(|(_, task)| task));
+    }
+    tasks
+}
+
+/*
+    Builds task trees from outlines. Open tasks are kept on a stack along
+    with the depth an entry has to go past to belong to them, e.g. the
+    column the text of a list item starts at. The tasks an entry does not
+    belong to are finished and moved into their parent.
+*/
+pub struct Outline {
+    pub lists: Vec<TaskList>,
+    pub open: Vec<(usize, Task)>,
+}
+
+impl Outline {
+    pub fn new(default_list: &str) -> Self {
+        Outline {
+            lists: vec![TaskList::new(default_list.to_string(), None)],
+            open: Vec::new(),
+        }
+    }
+
+    // closes the tasks an entry at this depth is not part of
+    pub fn close(&mut self, depth: usize) {
+        while self.open.last().is_some_and(|(open, _)| *open > depth) {
+            let (_, task) = self.open.pop().unwrap();
+            match self.open.last_mut() {
+                Some((_, parent)) => parent.sub_tasks.push(task),
+                None => self.lists.last_mut().unwrap().tasks.push(task),
+            }
+        }
+    }
+
+    pub fn start_list(&mut self, name: &str) {
+        self.close(0);
+        self.lists.push(TaskList::new(name.to_string(), None));
+    }
+
+    // lines that are not entries add to the notes of the last one
+    pub fn add_note(&mut self, line: &str) {
+        if let Some((_, task)) = self.open.last_mut() {
+            if !task.notes.is_empty() {
+                task.notes.push('\n');
+            }
+            task.notes.push_str(line.trim());
+        }
+    }
+
+    // the lists that ended up with tasks
+    pub fn finish(mut self) -> Vec<TaskList> {
+        self.close(0);
+        self.lists.retain(|list| !list.tasks.is_empty());
+        self.lists
+    }
+}
@@ -0,0 +1,318 @@
+use super::{TreeEntry, build_tree};
+use crate::tabs::{Duration, Priority, Task, TaskCommandError, TaskList, TaskStatus, parse_date};
+use chrono::{Local, NaiveDate};
+
+/*
+    Spreadsheets, one task per row:
+        list,path,name,status,date,duration,priority,tags,notes
+        Work,1,Release,In Progress,2025-01-15,00:02:30,High,"ops, web",
+        Work,1.1,Tag it,Finished,2025-01-15,,None,,
+    path is the position of the task within its list, so sub-tasks come
+    back below their parent. Reading goes by the header row, which may
+    name the columns the way other programs do ("Task Name", "Due Date",
+    "Done", ...), in any order and separated by commas, semicolons or
+    tabs. Only a name column is required, unknown columns are skipped.
+*/
+
+const HEADER: [&str; 9] = [
+    "list", "path", "name", "status", "date", "duration", "priority", "tags", "notes",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Column {
+    List,
+    Path,
+    Name,
+    Status,
+    Date,
+    Duration,
+    Priority,
+    Tags,
+    Notes,
+}
+
+fn column(header: &str) -> Option<Column> {
+    let header: String = header
+        .trim()
+        .to_ascii_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+    match header.as_str() {
+        "list" | "tasklist" | "project" | "category" => Some(Column::List),
+        "path" | "outline" | "wbs" => Some(Column::Path),
+        "name" | "title" | "task" | "taskname" | "summary" | "subject" | "description" => {
+            Some(Column::Name)
+        }
+        "status" | "state" | "done" | "completed" => Some(Column::Status),
+        "date" | "due" | "duedate" | "deadline" | "start" | "startdate" => Some(Column::Date),
+        "duration" | "estimate" | "effort" => Some(Column::Duration),
+        "priority" | "prio" => Some(Column::Priority),
+        "tags" | "tag" | "labels" | "contexts" => Some(Column::Tags),
+        "notes" | "note" | "details" | "comments" => Some(Column::Notes),
+        _ => None,
+    }
+}
+
+fn parse_status(value: &str) -> TaskStatus {
+    let value = value.trim().to_ascii_lowercase().replace([' ', '_'], "-");
+    match value.as_str() {
+        "x" | "yes" | "true" | "1" | "complete" | "completed" => TaskStatus::Finished,
+        "started" | "active" | "in-process" => TaskStatus::InProgress,
+        value => match TaskStatus::from_name(value) {
+            // a spreadsheet has no way to bring deleted tasks back
+            Some(TaskStatus::Deleted) | None => TaskStatus::NotStarted,
+            Some(status) => status,
+        },
+    }
+}
+
+// ISO dates, the usual spreadsheet layouts and the task editor shorthands
+fn parse_cell_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim().split([' ', 'T']).next()?;
+    ["%Y-%m-%d", "%Y/%m/%d", "%m/%d/%Y", "%d.%m.%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .or_else(|| parse_date(value))
+}
+
+fn format_duration(duration: &Duration) -> String {
+    if *duration == Duration::default() {
+        return String::new();
+    }
+    format!(
+        "{:02}:{:02}:{:02}",
+        duration.days, duration.hours, duration.minutes
+    )
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', ';', '\t', '"', '\n', '\r']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// the separator used most often in the header row
+fn detect_delimiter(text: &str) -> char {
+    let header = text.lines().next().unwrap_or_default();
+    let mut quoted = false;
+    let mut counts = [(',', 0), (';', 0), ('\t', 0)];
+    for c in header.chars() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if !quoted && let Some((_, count)) = counts.iter_mut().find(|(d, _)| *d == c) {
+            *count += 1;
+        }
+    }
+    counts
+        .iter()
+        .max_by_key(|(_, count)| *count)
+        .filter(|(_, count)| *count > 0)
+        .map_or(',', |(delimiter, _)| *delimiter)
+}
+
+// RFC 4180 records, quoted fields may hold separators, quotes and line breaks
+fn parse_records(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.next_if_eq(&'"').is_some() {
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            _ if quoted => field.push(c),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ if c == delimiter => record.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|record| record.iter().any(|field| !field.trim().is_empty()));
+    records
+}
+
+pub fn import(text: &str, default_list: &str) -> Result<Vec<TaskList>, TaskCommandError> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = parse_records(text, detect_delimiter(text)).into_iter();
+    let Some(header) = records.next() else {
+        return Ok(Vec::new());
+    };
+    let columns: Vec<Option<Column>> = header.iter().map(|name| column(name)).collect();
+    if !columns.contains(&Some(Column::Name)) {
+        return Err(TaskCommandError::InvalidFileFormat {
+            line: 0,
+            column: 0,
+            message: "no name or title column in the header".to_string(),
+        });
+    }
+
+    let today = Local::now().date_naive();
+    let mut lists: Vec<(String, Vec<TreeEntry>)> = Vec::new();
+    for record in records {
+        let mut list = default_list.to_string();
+        let mut path = None;
+        let mut task = Task {
+            date: today,
+            ..Task::default()
+        };
+        // the first column of each kind counts
+        let mut seen = Vec::new();
+        for (column, value) in columns.iter().zip(&record) {
+            let Some(column) = *column else {
+                continue;
+            };
+            if seen.contains(&column) {
+                continue;
+            }
+            seen.push(column);
+            let value = value.trim();
+            match column {
+                Column::List if !value.is_empty() => list = value.to_string(),
+                Column::Path if !value.is_empty() => path = Some(value.to_string()),
+                Column::Name => task.title = value.to_string(),
+                Column::Status => task.status = parse_status(value),
+                Column::Date => task.date = parse_cell_date(value).unwrap_or(today),
+                Column::Duration => task.duration = value.parse().unwrap_or_default(),
+                Column::Priority => task.priority = Priority::from_name(value).unwrap_or_default(),
+                Column::Tags => {
+                    for tag in value.split([',', ';', ' ']).filter(|tag| !tag.is_empty()) {
+                        task.add_tag(tag);
+                    }
+                }
+                Column::Notes => task.notes = value.to_string(),
+                _ => {}
+            }
+        }
+
+        let parent = path
+            .as_ref()
+            .and_then(|path| path.rsplit_once('.'))
+            .map(|(parent, _)| parent.to_string());
+        let entry = (path, parent, task);
+        match lists.iter_mut().find(|(name, _)| *name == list) {
+            Some((_, tasks)) => tasks.push(entry),
+            None => lists.push((list, vec![entry])),
+        }
+    }
+
+    Ok(lists
+        .into_iter()
+        .map(|(name, tasks)| TaskList::new(name, Some(build_tree(tasks))))
+        .collect())
+}
+
+fn write_tasks(list: &str, prefix: &str, tasks: &[Task], text: &mut String) {
+    let kept = tasks
+        .iter()
+        .filter(|task| task.status != TaskStatus::Deleted);
+    for (index, task) in kept.enumerate() {
+        let path = format!("{prefix}{}", index + 1);
+        let row = [
+            list.to_string(),
+            path.clone(),
+            task.title.clone(),
+            task.status.get_name().to_string(),
+            task.date.format("%Y-%m-%d").to_string(),
+            format_duration(&task.duration),
+            task.priority.get_name().to_string(),
+            task.tags.join(", "),
+            task.notes.clone(),
+        ];
+        let row: Vec<String> = row.iter().map(|field| quote(field)).collect();
+        text.push_str(&row.join(","));
+        text.push_str("\r\n");
+        write_tasks(list, &format!("{path}."), &task.sub_tasks, text);
+    }
+}
+
+pub fn export(task_lists: &[&TaskList]) -> String {
+    let mut text = HEADER.join(",");
+    text.push_str("\r\n");
+    for list in task_lists {
+        write_tasks(&list.name, "", &list.tasks, &mut text);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(title: &str) -> Task {
+        Task {
+            title: title.to_string(),
+            date: NaiveDate::from_ymd_opt(2025, 1, 15).unwrap(),
+            ..Task::default()
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let lists = vec![
+            TaskList::new(
+                "Work, mostly".to_string(),
+                Some(vec![Task {
+                    status: TaskStatus::InProgress,
+                    priority: Priority::High,
+                    tags: vec!["ops".to_string(), "web".to_string()],
+                    duration: "1:02:30".parse().unwrap(),
+                    sub_tasks: vec![
+                        Task {
+                            status: TaskStatus::Finished,
+                            notes: "said \"done\"\nreally".to_string(),
+                            sub_tasks: vec![task("Deepest")],
+                            ..task("Tag it")
+                        },
+                        task("Announce"),
+                    ],
+                    ..task("Release")
+                }]),
+            ),
+            TaskList::new("Home".to_string(), Some(vec![task("Dishes; then sleep")])),
+        ];
+        let text = export(&lists.iter().collect::<Vec<_>>());
+        assert!(import(&text, "other").unwrap() == lists);
+    }
+
+    #[test]
+    fn maps_columns_by_header() {
+        let text = "\u{feff}Task Name;Extra;Due Date;Done;Labels;Project\r\n\
+            Write report;ignored;03/04/2025;yes;work report;Office\r\n\
+            \"Call \"\"Bob\"\"; maybe\";;2025-03-05;;;\r\n\
+            \r\n";
+        let lists = import(text, "tasks").unwrap();
+        assert_eq!(lists.len(), 2);
+        assert_eq!(lists[0].name, "Office");
+        let report = &lists[0].tasks[0];
+        assert_eq!(report.title, "Write report");
+        assert_eq!(report.date, NaiveDate::from_ymd_opt(2025, 3, 4).unwrap());
+        assert!(report.status == TaskStatus::Finished);
+        assert_eq!(report.tags, ["work", "report"]);
+
+        assert_eq!(lists[1].name, "tasks");
+        assert_eq!(lists[1].tasks[0].title, "Call \"Bob\"; maybe");
+        assert!(lists[1].tasks[0].status == TaskStatus::NotStarted);
+    }
+
+    #[test]
+    fn needs_a_name_column() {
+        assert!(import("when,what\n1,2\n", "tasks").is_err());
+    }
+}
@@ -0,0 +1,601 @@
+use super::build_tree;
+use crate::{
+    recurrence::{Frequency, Recurrence},
+    tabs::{Duration, Priority, Task, TaskList, TaskStatus},
+};
+use chrono::{Local, NaiveDate, NaiveDateTime, Utc, Weekday};
+
+/*
+    iCalendar (RFC 5545) files. Tasks are written as VTODOs with their
+    date as DUE, sub-tasks pointing at their parent through RELATED-TO.
+    A VTODO may not have both DUE and DURATION, so the duration goes into
+    X-CLTODO-DURATION, which other programs ignore.
+
+    Reading takes VTODOs and VEVENTs (dated by DTSTART, lasting until
+    DTEND) into a single list named after the calendar. Cancelled ones
+    are skipped, as are repeat rules that cannot be expressed here.
+*/
+
+const DURATION_PROPERTY: &str = "X-CLTODO-DURATION";
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+// splits a list value at the commas that are not escaped
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                items.push(unescape(&value[start..index]));
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(unescape(&value[start..]));
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+// the date part of a DATE or DATE-TIME value
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+// DATE values start at midnight
+fn parse_date_time(value: &str) -> Option<NaiveDateTime> {
+    match value.get(..15) {
+        Some(time) => NaiveDateTime::parse_from_str(time, "%Y%m%dT%H%M%S").ok(),
+        None => parse_date(value)?.and_hms_opt(0, 0, 0),
+    }
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+fn duration_from_minutes(minutes: u64) -> Duration {
+    Duration {
+        days: (minutes / (24 * 60)).min(u16::MAX as u64) as u16,
+        hours: (minutes / 60 % 24) as u8,
+        minutes: (minutes % 60) as u8,
+    }
+}
+
+// P1DT2H30M or P2W, seconds are dropped
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.strip_prefix('+').unwrap_or(value);
+    let value = value.strip_prefix('P')?;
+    let mut minutes: u64 = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => in_time = true,
+            _ => {
+                let n = number.parse::<u64>().ok()?;
+                number.clear();
+                minutes += match (c, in_time) {
+                    ('W', false) => n * 7 * 24 * 60,
+                    ('D', false) => n * 24 * 60,
+                    ('H', true) => n * 60,
+                    ('M', true) => n,
+                    ('S', true) => n / 60,
+                    _ => return None,
+                };
+            }
+        }
+    }
+    number.is_empty().then(|| duration_from_minutes(minutes))
+}
+
+fn format_duration(duration: &Duration) -> String {
+    format!(
+        "P{}DT{}H{}M",
+        duration.days, duration.hours, duration.minutes
+    )
+}
+
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn parse_weekday(code: &str) -> Option<Weekday> {
+    [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+    .into_iter()
+    .find(|day| weekday_code(*day) == code)
+}
+
+fn format_rule(recurrence: &Recurrence) -> String {
+    let mut parts = vec![format!(
+        "FREQ={}",
+        match recurrence.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly(_) => "WEEKLY",
+            Frequency::Monthly(_) => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        }
+    )];
+    if recurrence.interval > 1 {
+        parts.push(format!("INTERVAL={}", recurrence.interval));
+    }
+    match &recurrence.frequency {
+        Frequency::Weekly(days) if !days.is_empty() => {
+            let codes: Vec<&str> = days.iter().map(|day| weekday_code(*day)).collect();
+            parts.push(format!("BYDAY={}", codes.join(",")));
+        }
+        Frequency::Monthly(Some(day)) => parts.push(format!("BYMONTHDAY={day}")),
+        _ => {}
+    }
+    if let Some(until) = recurrence.until {
+        parts.push(format!("UNTIL={}", format_date(until)));
+    }
+    parts.join(";")
+}
+
+// None for rules using parts a recurrence has no room for, like COUNT
+fn parse_rule(value: &str) -> Option<Recurrence> {
+    let mut frequency = None;
+    let mut interval = 1;
+    let mut days = Vec::new();
+    let mut month_day = None;
+    let mut until = None;
+    for part in value.split(';') {
+        match part.split_once('=')? {
+            ("FREQ", "DAILY") => frequency = Some(Frequency::Daily),
+            ("FREQ", "WEEKLY") => frequency = Some(Frequency::Weekly(Vec::new())),
+            ("FREQ", "MONTHLY") => frequency = Some(Frequency::Monthly(None)),
+            ("FREQ", "YEARLY") => frequency = Some(Frequency::Yearly),
+            ("INTERVAL", n) => interval = n.parse::<u32>().ok().filter(|n| *n > 0)?,
+            ("BYDAY", codes) => {
+                for code in codes.split(',') {
+                    days.push(parse_weekday(code)?);
+                }
+            }
+            ("BYMONTHDAY", day) => month_day = Some(day.parse::<u32>().ok()?),
+            ("UNTIL", date) => until = Some(parse_date(date)?),
+            ("WKST", _) => {}
+            _ => return None,
+        }
+    }
+    let frequency = match (frequency?, days.is_empty(), month_day) {
+        (Frequency::Weekly(_), _, None) => Frequency::Weekly(days),
+        (Frequency::Monthly(_), true, day) => Frequency::Monthly(day),
+        (frequency, true, None) => frequency,
+        _ => return None,
+    };
+    Some(Recurrence {
+        frequency,
+        interval,
+        until,
+        anchor: None,
+    })
+}
+
+// 1 is the highest priority and 9 the lowest, 0 means none
+fn priority_value(priority: Priority) -> u8 {
+    match priority {
+        Priority::None => 0,
+        Priority::Critical => 1,
+        Priority::High => 3,
+        Priority::Medium => 5,
+        Priority::Low => 9,
+    }
+}
+
+fn parse_priority(value: &str) -> Priority {
+    match value.trim().parse::<u8>() {
+        Ok(1..=2) => Priority::Critical,
+        Ok(3..=4) => Priority::High,
+        Ok(5) => Priority::Medium,
+        Ok(6..=9) => Priority::Low,
+        _ => Priority::None,
+    }
+}
+
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// NAME;PARAM=a;PARAM="b:c":value
+fn parse_property(line: &str) -> Option<Property> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(index, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(index),
+        _ => None,
+    })?;
+    let mut head = line[..colon].split(';');
+    let name = head.next()?.trim().to_ascii_uppercase();
+    let params = head
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.trim_matches('"').to_string()))
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value: line[colon + 1..].to_string(),
+    })
+}
+
+// joins folded lines, which continue on lines starting with a space or tab
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+// a VTODO or VEVENT as far as it is read so far
+#[derive(Default)]
+struct Component {
+    event: bool,
+    uid: Option<String>,
+    parent: Option<String>,
+    task: Task,
+    due: Option<NaiveDate>,
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+    cancelled: bool,
+}
+
+impl Component {
+    fn read(&mut self, property: Property) {
+        let task = &mut self.task;
+        let value = property.value.as_str();
+        match property.name.as_str() {
+            "UID" => self.uid = Some(value.to_string()),
+            "SUMMARY" => task.title = unescape(value),
+            "DESCRIPTION" => task.notes = unescape(value),
+            "DUE" => self.due = parse_date(value),
+            "DTSTART" => self.start = parse_date_time(value),
+            "DTEND" => self.end = parse_date_time(value),
+            "DURATION" | DURATION_PROPERTY => {
+                task.duration = parse_duration(value).unwrap_or(task.duration)
+            }
+            "STATUS" => match value {
+                "IN-PROCESS" => task.status = TaskStatus::InProgress,
+                "COMPLETED" => task.status = TaskStatus::Finished,
+                "CANCELLED" => self.cancelled = true,
+                _ => task.status = TaskStatus::NotStarted,
+            },
+            "PRIORITY" => task.priority = parse_priority(value),
+            "CATEGORIES" => {
+                for tag in split_list(value) {
+                    task.add_tag(&tag);
+                }
+            }
+            "RRULE" => task.recurrence = parse_rule(value),
+            "RELATED-TO"
+                if property
+                    .param("RELTYPE")
+                    .is_none_or(|kind| kind.eq_ignore_ascii_case("PARENT")) =>
+            {
+                self.parent = Some(value.to_string())
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn import(text: &str, default_list: &str) -> Vec<TaskList> {
+    let today = Local::now().date_naive();
+    let mut name = default_list.to_string();
+    let mut components = Vec::new();
+    // the components the current line is in, innermost last
+    let mut open: Vec<String> = Vec::new();
+    let mut current: Option<Component> = None;
+
+    for line in unfold(text) {
+        let Some(property) = parse_property(&line) else {
+            continue;
+        };
+        match property.name.as_str() {
+            "BEGIN" => {
+                let kind = property.value.trim().to_ascii_uppercase();
+                if open.len() == 1 && (kind == "VTODO" || kind == "VEVENT") {
+                    current = Some(Component {
+                        event: kind == "VEVENT",
+                        ..Component::default()
+                    });
+                }
+                open.push(kind);
+            }
+            "END" => {
+                open.pop();
+                if open.len() == 1
+                    && let Some(component) = current.take()
+                {
+                    components.push(component);
+                }
+            }
+            "X-WR-CALNAME" if open.len() == 1 => name = unescape(&property.value),
+            _ if open.len() == 2 => {
+                if let Some(component) = current.as_mut() {
+                    component.read(property);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut tasks = Vec::new();
+    for mut component in components {
+        if component.cancelled {
+            continue;
+        }
+        let start = component.start.map(|start| start.date());
+        component.task.date = match component.event {
+            true => start.or(component.due),
+            false => component.due.or(start),
+        }
+        .unwrap_or(today);
+        // events last until DTEND unless they have a DURATION
+        if component.event
+            && component.task.duration == Duration::default()
+            && let (Some(start), Some(end)) = (component.start, component.end)
+        {
+            let minutes = end.signed_duration_since(start).num_minutes().max(0);
+            component.task.duration = duration_from_minutes(minutes as u64);
+        }
+        tasks.push((component.uid, component.parent, component.task));
+    }
+
+    let tasks = build_tree(tasks);
+    if tasks.is_empty() {
+        return Vec::new();
+    }
+    vec![TaskList::new(name, Some(tasks))]
+}
+
+// lines are limited to 75 bytes, longer ones continue after a space
+fn push_line(text: &mut String, line: &str) {
+    let mut rest = line;
+    let mut limit = 75;
+    while rest.len() > limit {
+        let mut split = limit;
+        while !rest.is_char_boundary(split) {
+            split -= 1;
+        }
+        text.push_str(&rest[..split]);
+        text.push_str("\r\n ");
+        rest = &rest[split..];
+        limit = 74;
+    }
+    text.push_str(rest);
+    text.push_str("\r\n");
+}
+
+fn write_task(task: &Task, parent: Option<u64>, stamp: &str, text: &mut String) {
+    if task.status != TaskStatus::Deleted {
+        push_line(text, "BEGIN:VTODO");
+        push_line(text, &format!("UID:cltodo-{}", task.id));
+        push_line(text, &format!("DTSTAMP:{stamp}"));
+        push_line(text, &format!("SUMMARY:{}", escape(&task.title)));
+        if !task.notes.is_empty() {
+            push_line(text, &format!("DESCRIPTION:{}", escape(&task.notes)));
+        }
+        push_line(text, &format!("DUE;VALUE=DATE:{}", format_date(task.date)));
+        let status = match task.status {
+            TaskStatus::InProgress => "IN-PROCESS",
+            TaskStatus::Finished => "COMPLETED",
+            _ => "NEEDS-ACTION",
+        };
+        push_line(text, &format!("STATUS:{status}"));
+        if task.priority != Priority::None {
+            push_line(text, &format!("PRIORITY:{}", priority_value(task.priority)));
+        }
+        if !task.tags.is_empty() {
+            let tags: Vec<String> = task.tags.iter().map(|tag| escape(tag)).collect();
+            push_line(text, &format!("CATEGORIES:{}", tags.join(",")));
+        }
+        if task.duration != Duration::default() {
+            push_line(
+                text,
+                &format!("{DURATION_PROPERTY}:{}", format_duration(&task.duration)),
+            );
+        }
+        if let Some(recurrence) = &task.recurrence {
+            push_line(text, &format!("RRULE:{}", format_rule(recurrence)));
+        }
+        if let Some(parent) = parent {
+            push_line(text, &format!("RELATED-TO;RELTYPE=PARENT:cltodo-{parent}"));
+        }
+        push_line(text, "END:VTODO");
+    }
+    for sub_task in &task.sub_tasks {
+        write_task(sub_task, Some(task.id), stamp, text);
+    }
+}
+
+pub fn export(task_lists: &[&TaskList]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut text = String::new();
+    push_line(&mut text, "BEGIN:VCALENDAR");
+    push_line(&mut text, "VERSION:2.0");
+    push_line(&mut text, "PRODID:-//cltodo//cltodo//EN");
+    if let [list] = task_lists {
+        push_line(&mut text, &format!("X-WR-CALNAME:{}", escape(&list.name)));
+    }
+    for list in task_lists {
+        for task in &list.tasks {
+            write_task(task, None, &stamp, &mut text);
+        }
+    }
+    push_line(&mut text, "END:VCALENDAR");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//Example//Calendar//EN\r
+X-WR-CALNAME:Home\r
+BEGIN:VTODO\r
+UID:a1\r
+SUMMARY:Plan the trip\\, finally\r
+DESCRIPTION:Flights first\\nthen hotels\r
+DUE;TZID=Europe/Berlin:20250301T120000\r
+STATUS:IN-PROCESS\r
+PRIORITY:2\r
+CATEGORIES:travel,family\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+DESCRIPTION:not the task\r
+END:VALARM\r
+END:VTODO\r
+BEGIN:VTODO\r
+UID:a2\r
+RELATED-TO:a1\r
+SUMMARY:Book flights with a long title that has to be folded over more than\r
+  one line\r
+DUE;VALUE=DATE:20250210\r
+STATUS:COMPLETED\r
+END:VTODO\r
+BEGIN:VTODO\r
+UID:a3\r
+SUMMARY:Dropped\r
+STATUS:CANCELLED\r
+END:VTODO\r
+BEGIN:VEVENT\r
+UID:e1\r
+SUMMARY:Conference\r
+DTSTART;VALUE=DATE:20250402\r
+DTEND;VALUE=DATE:20250404\r
+RRULE:FREQ=YEARLY\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:e2\r
+SUMMARY:Standup\r
+DTSTART:20250403T090000Z\r
+DURATION:PT15M\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,WE;COUNT=10\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn date(text: &str) -> NaiveDate {
+        parse_date(text).unwrap()
+    }
+
+    #[test]
+    fn imports_todos_and_events() {
+        let lists = import(SAMPLE, "calendar");
+        assert_eq!(lists.len(), 1);
+        assert_eq!(lists[0].name, "Home");
+        let tasks = &lists[0].tasks;
+        let titles: Vec<&str> = tasks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["Plan the trip, finally", "Conference", "Standup"]);
+
+        let trip = &tasks[0];
+        assert_eq!(trip.notes, "Flights first\nthen hotels");
+        assert_eq!(trip.date, date("20250301"));
+        assert!(trip.status == TaskStatus::InProgress);
+        assert_eq!(trip.priority, Priority::Critical);
+        assert_eq!(trip.tags, ["travel", "family"]);
+        assert_eq!(trip.sub_tasks.len(), 1);
+        let flights = &trip.sub_tasks[0];
+        assert_eq!(
+            flights.title,
+            "Book flights with a long title that has to be folded over more than one line"
+        );
+        assert!(flights.status == TaskStatus::Finished);
+
+        let conference = &tasks[1];
+        assert_eq!(conference.date, date("20250402"));
+        assert_eq!(conference.duration.days, 2);
+        assert_eq!(conference.recurrence, "yearly".parse().ok());
+
+        let standup = &tasks[2];
+        assert_eq!(standup.duration.minutes, 15);
+        // COUNT cannot be kept, so the rule is left out
+        assert_eq!(standup.recurrence, None);
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut lists = import(SAMPLE, "calendar");
+        crate::tabs::assign_task_ids(&mut lists);
+        lists[0].tasks[0].recurrence = "weekly/2 mon,thu until 20251231".parse().ok();
+        lists[0].tasks[0].sub_tasks[0].recurrence = "monthly 15".parse().ok();
+        let exported = export(&[&lists[0]]);
+        assert!(exported.lines().all(|line| line.len() <= 75));
+
+        let mut again = import(&exported, "calendar");
+        crate::tabs::assign_task_ids(&mut again);
+        // ids are handed out afresh, everything else carries over
+        fn clear_ids(tasks: &mut [Task]) {
+            for task in tasks {
+                task.id = 0;
+                clear_ids(&mut task.sub_tasks);
+            }
+        }
+        clear_ids(&mut lists[0].tasks);
+        clear_ids(&mut again[0].tasks);
+        assert!(again == lists);
+    }
+}
@@ -0,0 +1,346 @@
+use super::Outline;
+use crate::tabs::{Duration, Task, TaskList, TaskStatus};
+use chrono::{Local, NaiveDate};
+
+/*
+    Markdown checklists, a heading per list:
+        # Work
+        - [ ] Release notes _(2025-01-15, 00:02:30)_
+          - [x] Collect merged PRs
+            Notes are indented lines below their task.
+    The group at the end holds the date, the duration when there is one
+    and "in progress" for started tasks, since checkboxes only know done
+    and not done. Any list marker (-, *, + or 1.) and indentation is read,
+    so checklists written elsewhere import too. Other lines are skipped.
+    Notes with blank or indented lines, or lines that would be read as an
+    item, heading or fence, are written in a fenced block and read back
+    as they are.
+*/
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const IN_PROGRESS: &str = "in progress";
+
+// width of the leading whitespace, tabs counting as four spaces
+fn indent_of(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+// the line without the first width columns of its indentation
+fn dedent(line: &str, width: usize) -> &str {
+    let mut column = 0;
+    let start = line
+        .find(|c: char| {
+            if column >= width || !c.is_whitespace() {
+                return true;
+            }
+            column += if c == '\t' { 4 } else { 1 };
+            false
+        })
+        .unwrap_or(line.len());
+    &line[start..]
+}
+
+// the number of backticks of a ``` line
+fn fence_width(line: &str) -> Option<usize> {
+    let line = line.trim();
+    (line.len() >= 3 && line.chars().all(|c| c == '`')).then_some(line.len())
+}
+
+// notes that would not be read back as they are from plain lines
+fn needs_fence(notes: &str) -> bool {
+    notes.split('\n').any(|line| {
+        line.trim().is_empty()
+            || line != line.trim()
+            || line.starts_with('#')
+            || parse_item(line).is_some()
+            || fence_width(line).is_some()
+    })
+}
+
+// the lines of a fenced block are the notes as they were written
+fn add_block(builder: &mut Outline, lines: &[&str]) {
+    if let Some((_, task)) = builder.open.last_mut() {
+        if !task.notes.is_empty() {
+            task.notes.push('\n');
+        }
+        task.notes.push_str(&lines.join("\n"));
+    }
+}
+
+// the width of the marker, the status and the rest of a "- [ ] ..." item
+fn parse_item(line: &str) -> Option<(usize, TaskStatus, &str)> {
+    let line = line.trim_start();
+    let rest = match line.strip_prefix(['-', '*', '+']) {
+        Some(rest) => rest,
+        None => {
+            let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits == 0 {
+                return None;
+            }
+            line[digits..].strip_prefix(['.', ')'])?
+        }
+    };
+    let rest = rest.strip_prefix(' ')?;
+    let marker = line.len() - rest.len();
+    let rest = rest.trim_start();
+    let status = match rest.get(..3)? {
+        "[ ]" => TaskStatus::NotStarted,
+        "[x]" | "[X]" => TaskStatus::Finished,
+        "[-]" | "[~]" | "[/]" => TaskStatus::InProgress,
+        _ => return None,
+    };
+    let title = &rest[3..];
+    if !title.is_empty() && !title.starts_with(' ') {
+        return None;
+    }
+    Some((marker, status, title.trim()))
+}
+
+// fills in the task from the trailing _(...)_ group, if the line has one
+fn parse_title(text: &str, task: &mut Task) {
+    task.title = text.to_string();
+    let Some(group) = text.strip_suffix(")_") else {
+        return;
+    };
+    let Some((title, group)) = group.rsplit_once(" _(") else {
+        return;
+    };
+
+    let mut date = None;
+    let mut duration = None;
+    let mut in_progress = false;
+    for part in group.split(", ") {
+        if part == IN_PROGRESS {
+            in_progress = true;
+        } else if let Ok(parsed) = NaiveDate::parse_from_str(part, DATE_FORMAT) {
+            date = Some(parsed);
+        } else if part.contains(':')
+            && let Ok(parsed) = part.parse::<Duration>()
+        {
+            duration = Some(parsed);
+        } else {
+            // just parentheses in the title
+            return;
+        }
+    }
+
+    task.title = title.trim_end().to_string();
+    if let Some(date) = date {
+        task.date = date;
+    }
+    if let Some(duration) = duration {
+        task.duration = duration;
+    }
+    if in_progress && task.status == TaskStatus::NotStarted {
+        task.status = TaskStatus::InProgress;
+    }
+}
+
+pub fn import(text: &str, default_list: &str) -> Vec<TaskList> {
+    // items are closed by the column their text starts at, as in CommonMark
+    let mut builder = Outline::new(default_list);
+    let today = Local::now().date_naive();
+    // the column and width of an open fence and the lines so far
+    let mut fence: Option<(usize, usize, Vec<&str>)> = None;
+
+    for line in text.lines() {
+        if let Some((column, width, lines)) = &mut fence {
+            if fence_width(line).is_some_and(|closing| closing >= *width) {
+                add_block(&mut builder, lines);
+                fence = None;
+            } else {
+                lines.push(dedent(line, *column));
+            }
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = indent_of(line);
+        if indent == 0
+            && let Some(heading) = line.strip_prefix('#')
+        {
+            let name = heading.trim_start_matches('#');
+            if name.starts_with(' ') && !name.trim().is_empty() {
+                builder.start_list(name.trim());
+                continue;
+            }
+        }
+
+        builder.close(indent);
+        match parse_item(line) {
+            Some((marker, status, title)) => {
+                let mut task = Task {
+                    status,
+                    date: today,
+                    ..Task::default()
+                };
+                parse_title(title, &mut task);
+                builder.open.push((indent + marker, task));
+            }
+            // text below an item is part of its notes
+            None => match fence_width(line) {
+                Some(width) if !builder.open.is_empty() => {
+                    fence = Some((indent, width, Vec::new()));
+                }
+                _ => builder.add_note(line),
+            },
+        }
+    }
+    if let Some((_, _, lines)) = fence {
+        add_block(&mut builder, &lines);
+    }
+    builder.finish()
+}
+
+fn write_task(task: &Task, depth: usize, text: &mut String) {
+    if task.status == TaskStatus::Deleted {
+        return;
+    }
+    let indent = "  ".repeat(depth);
+    let check = if task.status == TaskStatus::Finished {
+        'x'
+    } else {
+        ' '
+    };
+    let mut meta = Vec::new();
+    if task.status == TaskStatus::InProgress {
+        meta.push(IN_PROGRESS.to_string());
+    }
+    meta.push(task.date.format(DATE_FORMAT).to_string());
+    if task.duration != Duration::default() {
+        meta.push(task.duration.to_string());
+    }
+    text.push_str(&format!(
+        "{indent}- [{check}] {} _({})_\n",
+        task.title,
+        meta.join(", ")
+    ));
+    if !task.notes.is_empty() && needs_fence(&task.notes) {
+        // longer than any run of backticks in the notes
+        let width = task.notes.split('\n').filter_map(fence_width).max();
+        let fence = "`".repeat(width.map_or(3, |width| width + 1));
+        text.push_str(&format!("{indent}  {fence}\n"));
+        for line in task.notes.split('\n') {
+            match line.is_empty() {
+                true => text.push('\n'),
+                false => text.push_str(&format!("{indent}  {line}\n")),
+            }
+        }
+        text.push_str(&format!("{indent}  {fence}\n"));
+    } else {
+        for line in task.notes.lines() {
+            text.push_str(&format!("{indent}  {line}\n"));
+        }
+    }
+    for sub_task in &task.sub_tasks {
+        write_task(sub_task, depth + 1, text);
+    }
+}
+
+pub fn export(task_lists: &[&TaskList]) -> String {
+    let mut sections = Vec::new();
+    for list in task_lists {
+        let mut text = format!("# {}\n", list.name);
+        if !list.tasks.is_empty() {
+            text.push('\n');
+        }
+        for task in &list.tasks {
+            write_task(task, 0, &mut text);
+        }
+        sections.push(text);
+    }
+    sections.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+Intro text that is not a task.
+
+- [ ] Loose item
+
+## Release
+1. [x] Tag the release
+   * [ ] Update changelog _(in progress, 2025-02-01, 00:01:30)_
+     - [X] Draft
+     Needs a second pair of eyes.
+\t* [ ] Announce (after the tag)
+- [ ] Not [x] a checkbox title
+- plain bullet
+#not-a-heading
+-[ ] not an item either
+";
+
+    fn titles(tasks: &[Task]) -> Vec<&str> {
+        tasks.iter().map(|t| t.title.as_str()).collect()
+    }
+
+    #[test]
+    fn imports_nested_checklists() {
+        let lists = import(SAMPLE, "notes");
+        assert_eq!(lists.len(), 2);
+        assert_eq!(lists[0].name, "notes");
+        assert_eq!(titles(&lists[0].tasks), ["Loose item"]);
+
+        let release = &lists[1];
+        assert_eq!(release.name, "Release");
+        assert_eq!(
+            titles(&release.tasks),
+            ["Tag the release", "Not [x] a checkbox title"]
+        );
+        let tag = &release.tasks[0];
+        assert!(tag.status == TaskStatus::Finished);
+        assert_eq!(
+            titles(&tag.sub_tasks),
+            ["Update changelog", "Announce (after the tag)"]
+        );
+
+        let changelog = &tag.sub_tasks[0];
+        assert!(changelog.status == TaskStatus::InProgress);
+        assert_eq!(changelog.date.format(DATE_FORMAT).to_string(), "2025-02-01");
+        assert_eq!(changelog.duration.to_string(), "00:01:30");
+        assert_eq!(changelog.notes, "Needs a second pair of eyes.");
+        assert_eq!(titles(&changelog.sub_tasks), ["Draft"]);
+        assert!(changelog.sub_tasks[0].status == TaskStatus::Finished);
+    }
+
+    #[test]
+    fn round_trips() {
+        let imported = import(SAMPLE, "notes");
+        let exported = export(&imported.iter().collect::<Vec<_>>());
+        assert!(import(&exported, "other") == imported);
+    }
+
+    #[test]
+    fn notes_that_look_like_items_round_trip() {
+        let task = |title: &str, notes: &str, sub_tasks| Task {
+            title: title.to_string(),
+            notes: notes.to_string(),
+            sub_tasks,
+            ..Task::default()
+        };
+        let lists = vec![TaskList::new(
+            "Calls".to_string(),
+            Some(vec![
+                task(
+                    "Phone round",
+                    "- [ ] call Bob\n# not a heading",
+                    vec![task(
+                        "Ask about",
+                        "first\n\n    indented\n```\ncode\n````\n",
+                        vec![],
+                    )],
+                ),
+                task("Plain", "1. [x] done already", vec![]),
+            ]),
+        )];
+        let exported = export(&lists.iter().collect::<Vec<_>>());
+        assert!(import(&exported, "other") == lists);
+    }
+}
@@ -0,0 +1,452 @@
+use super::{
+    Outline,
+    todotxt::{parse_recurrence, priority_letter, recurrence_value},
+};
+use crate::tabs::{Duration, Priority, Task, TaskList, TaskStatus};
+use chrono::{Local, NaiveDate};
+use std::collections::HashMap;
+
+/*
+    Emacs Org files, a top level heading per list:
+        #+TODO: TODO DOING | DONE
+        * Work
+        ** DOING [#B] Release notes :ops:web:
+        DEADLINE: <2025-01-15 Wed +1w>
+        :PROPERTIES:
+        :EFFORT:   2:30
+        :END:
+        Notes are the text below the headline.
+        *** DONE Collect merged PRs
+    Sub-tasks are deeper headlines, the date is the deadline and the
+    duration the effort. Simple repeating rules become a repeater on the
+    deadline. Reading takes the keywords of #+TODO lines into account, a
+    top level headline without a keyword starts a new list. Notes with
+    lines that would be read as a headline, comment, drawer or planning
+    line are written in an example block, with Org's comma escapes.
+*/
+
+const HEADER: &str = "#+TODO: TODO DOING | DONE\n#+PRIORITIES: A E E\n";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const PLANNING: [&str; 3] = ["DEADLINE:", "SCHEDULED:", "CLOSED:"];
+
+fn keyword(status: &TaskStatus) -> &str {
+    match status {
+        TaskStatus::InProgress => "DOING",
+        TaskStatus::Finished => "DONE",
+        _ => "TODO",
+    }
+}
+
+// the keywords Org users commonly set up, #+TODO lines add their own
+fn default_keywords() -> HashMap<String, TaskStatus> {
+    let keywords = [
+        ("TODO", TaskStatus::NotStarted),
+        ("NEXT", TaskStatus::NotStarted),
+        ("WAIT", TaskStatus::NotStarted),
+        ("WAITING", TaskStatus::NotStarted),
+        ("HOLD", TaskStatus::NotStarted),
+        ("DOING", TaskStatus::InProgress),
+        ("STARTED", TaskStatus::InProgress),
+        ("DONE", TaskStatus::Finished),
+        ("CANCELED", TaskStatus::Deleted),
+        ("CANCELLED", TaskStatus::Deleted),
+    ];
+    keywords
+        .into_iter()
+        .map(|(word, status)| (word.to_string(), status))
+        .collect()
+}
+
+// "#+TODO: TODO(t) WAIT | DONE(d)", the states after the bar are done
+fn add_keywords(line: &str, keywords: &mut HashMap<String, TaskStatus>) {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let bar = words.iter().position(|word| *word == "|");
+    for (index, word) in words.iter().enumerate() {
+        let word = word.split('(').next().unwrap_or_default();
+        if word.is_empty() || word == "|" {
+            continue;
+        }
+        // without a bar only the last state is done
+        let done = match bar {
+            Some(bar) => index > bar,
+            None => index == words.len() - 1,
+        };
+        let status = match done {
+            true => TaskStatus::Finished,
+            false => TaskStatus::NotStarted,
+        };
+        keywords.entry(word.to_string()).or_insert(status);
+    }
+}
+
+// org only allows letters, digits, _, @, # and % in tags
+fn tag_name(tag: &str) -> String {
+    tag.chars()
+        .map(|c| match c.is_alphanumeric() || "_@#%".contains(c) {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+// "1d 2:30", the layout Org uses for durations
+fn format_effort(duration: &Duration) -> String {
+    let time = format!("{}:{:02}", duration.hours, duration.minutes);
+    match duration.days {
+        0 => time,
+        days => format!("{days}d {time}"),
+    }
+}
+
+// "2:30", "1d 2:30", "3h" or "45min"
+fn parse_effort(value: &str) -> Option<Duration> {
+    let (mut days, mut hours, mut minutes) = (0u16, 0u16, 0u16);
+    for part in value.split_whitespace() {
+        if let Some((h, m)) = part.split_once(':') {
+            hours += h.parse::<u16>().ok()?;
+            minutes += m.parse::<u16>().ok()?;
+        } else if let Some(d) = part.strip_suffix('d') {
+            days += d.parse::<u16>().ok()?;
+        } else if let Some(h) = part.strip_suffix('h') {
+            hours += h.parse::<u16>().ok()?;
+        } else if let Some(m) = part.strip_suffix("min") {
+            minutes += m.parse::<u16>().ok()?;
+        } else {
+            return None;
+        }
+    }
+    format!("{days}:{hours}:{minutes}").parse().ok()
+}
+
+// the date and repeater of the timestamp after a DEADLINE: or SCHEDULED:
+fn parse_planning(line: &str, name: &str, task: &mut Task) -> bool {
+    let Some((_, rest)) = line.split_once(name) else {
+        return false;
+    };
+    let Some(stamp) = rest
+        .trim_start()
+        .strip_prefix('<')
+        .and_then(|rest| rest.split_once('>'))
+        .map(|(stamp, _)| stamp)
+    else {
+        return false;
+    };
+    let mut words = stamp.split_whitespace();
+    let Some(date) = words
+        .next()
+        .and_then(|date| NaiveDate::parse_from_str(date, DATE_FORMAT).ok())
+    else {
+        return false;
+    };
+    task.date = date;
+    // +1w, ++1w and .+1w all repeat every week
+    if let Some(repeater) = words.find(|word| word.starts_with(['+', '.'])) {
+        task.recurrence = parse_recurrence(repeater.trim_start_matches(['+', '.']));
+    }
+    true
+}
+
+fn is_drawer(line: &str) -> bool {
+    line.len() > 2 && line.starts_with(':') && line.ends_with(':')
+}
+
+// a notes line that would not be read back as one
+fn needs_block(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with(['*', '#'])
+        || is_drawer(line)
+        || PLANNING.iter().any(|name| line.starts_with(name))
+}
+
+// lines in blocks starting with * or #+ get a comma in front, and so do
+// lines that already start with commas before those
+fn needs_comma(line: &str) -> bool {
+    let line = line.trim_start_matches(',');
+    line.starts_with('*') || line.starts_with("#+")
+}
+
+// the level and the text of a "** ..." headline
+fn parse_headline(line: &str) -> Option<(usize, &str)> {
+    let text = line.trim_start_matches('*');
+    let level = line.len() - text.len();
+    if level == 0 || !text.starts_with(' ') {
+        return None;
+    }
+    Some((level, text.trim()))
+}
+
+// the keyword, priority cookie and tags around the title
+fn parse_task(text: &str, keywords: &HashMap<String, TaskStatus>) -> (Option<TaskStatus>, Task) {
+    let mut task = Task::default();
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+
+    let status = words.first().and_then(|word| keywords.get(*word)).cloned();
+    if status.is_some() {
+        words.remove(0);
+    }
+    if let Some(cookie) = words
+        .first()
+        .and_then(|word| word.strip_prefix("[#"))
+        .and_then(|word| word.strip_suffix(']'))
+    {
+        task.priority = match cookie {
+            "A" => Priority::Critical,
+            "B" => Priority::High,
+            "C" => Priority::Medium,
+            "D" => Priority::Low,
+            _ => Priority::None,
+        };
+        words.remove(0);
+    }
+    if let Some(tags) = words
+        .last()
+        .filter(|word| word.len() > 2 && word.starts_with(':') && word.ends_with(':'))
+    {
+        for tag in tags.split(':').filter(|tag| !tag.is_empty()) {
+            task.add_tag(tag);
+        }
+        words.pop();
+    }
+
+    task.title = words.join(" ");
+    task.status = status.clone().unwrap_or(TaskStatus::NotStarted);
+    (status, task)
+}
+
+pub fn import(text: &str, default_list: &str) -> Vec<TaskList> {
+    let mut outline = Outline::new(default_list);
+    let mut keywords = default_keywords();
+    let today = Local::now().date_naive();
+    let mut drawer = false;
+    let mut block = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if block {
+            if trimmed.to_ascii_uppercase().starts_with("#+END_") {
+                block = false;
+            } else if !trimmed.is_empty() {
+                let escaped = trimmed.strip_prefix(',').filter(|rest| needs_comma(rest));
+                outline.add_note(escaped.unwrap_or(trimmed));
+            }
+        } else if let Some((level, text)) = parse_headline(line) {
+            drawer = false;
+            let (status, mut task) = parse_task(text, &keywords);
+            if level == 1 && status.is_none() {
+                outline.start_list(text);
+                continue;
+            }
+            task.date = today;
+            outline.close(level - 1);
+            outline.open.push((level, task));
+        } else if drawer {
+            drawer = trimmed != ":END:";
+            if let Some(effort) = trimmed.strip_prefix(":EFFORT:")
+                && let Some((_, task)) = outline.open.last_mut()
+            {
+                task.duration = parse_effort(effort).unwrap_or_default();
+            }
+        } else if let Some(words) = ["#+TODO:", "#+SEQ_TODO:", "#+TYP_TODO:"]
+            .iter()
+            .find_map(|name| line.strip_prefix(name))
+        {
+            add_keywords(words, &mut keywords);
+        } else if trimmed.to_ascii_uppercase().starts_with("#+BEGIN_") {
+            block = true;
+        } else if trimmed.is_empty() || line.starts_with('#') {
+            continue;
+        } else if is_drawer(trimmed) {
+            drawer = true;
+        } else if let Some((_, task)) = outline.open.last_mut()
+            && task.notes.is_empty()
+            && PLANNING.iter().any(|name| trimmed.starts_with(name))
+        {
+            if !parse_planning(trimmed, "DEADLINE:", task) {
+                parse_planning(trimmed, "SCHEDULED:", task);
+            }
+        } else {
+            outline.add_note(line);
+        }
+    }
+    outline.finish()
+}
+
+fn write_task(task: &Task, level: usize, text: &mut String) {
+    if task.status == TaskStatus::Deleted {
+        return;
+    }
+    let mut headline = vec!["*".repeat(level), keyword(&task.status).to_string()];
+    if let Some(cookie) = priority_letter(task.priority) {
+        headline.push(format!("[#{cookie}]"));
+    }
+    headline.push(task.title.clone());
+    if !task.tags.is_empty() {
+        let tags: Vec<String> = task.tags.iter().map(|tag| tag_name(tag)).collect();
+        headline.push(format!(":{}:", tags.join(":")));
+    }
+    text.push_str(&headline.join(" "));
+    text.push('\n');
+
+    let mut stamp = task.date.format("%Y-%m-%d %a").to_string();
+    if let Some(repeater) = task.recurrence.as_ref().and_then(recurrence_value) {
+        stamp.push_str(&format!(" +{repeater}"));
+    }
+    text.push_str(&format!("DEADLINE: <{stamp}>\n"));
+    if task.duration != Duration::default() {
+        text.push_str(&format!(
+            ":PROPERTIES:\n:EFFORT:   {}\n:END:\n",
+            format_effort(&task.duration)
+        ));
+    }
+    let notes: Vec<&str> = task
+        .notes
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let block = notes.iter().any(|line| needs_block(line));
+    if block {
+        text.push_str("#+BEGIN_EXAMPLE\n");
+    }
+    for line in notes {
+        let comma = if block && needs_comma(line) { "," } else { "" };
+        text.push_str(&format!("{comma}{line}\n"));
+    }
+    if block {
+        text.push_str("#+END_EXAMPLE\n");
+    }
+    for sub_task in &task.sub_tasks {
+        write_task(sub_task, level + 1, text);
+    }
+}
+
+pub fn export(task_lists: &[&TaskList]) -> String {
+    let mut text = HEADER.to_string();
+    for list in task_lists {
+        text.push_str(&format!("* {}\n", list.name));
+        for task in &list.tasks {
+            write_task(task, 2, &mut text);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let lists = vec![TaskList::new(
+            "Garden".to_string(),
+            Some(vec![
+                Task {
+                    title: "Prune roses".to_string(),
+                    status: TaskStatus::InProgress,
+                    priority: Priority::High,
+                    tags: vec!["outside".to_string(), "spring".to_string()],
+                    date: date(8),
+                    duration: "1:02:30".parse().unwrap(),
+                    recurrence: "yearly/2".parse().ok(),
+                    notes: "Gloves first".to_string(),
+                    sub_tasks: vec![Task {
+                        title: "Sharpen shears".to_string(),
+                        status: TaskStatus::Finished,
+                        priority: Priority::Low,
+                        date: date(7),
+                        ..Task::default()
+                    }],
+                    ..Task::default()
+                },
+                Task {
+                    title: "Order seeds".to_string(),
+                    date: date(1),
+                    ..Task::default()
+                },
+            ]),
+        )];
+        let text = export(&lists.iter().collect::<Vec<_>>());
+        assert!(text.contains(
+            "** DOING [#B] Prune roses :outside:spring:\nDEADLINE: <2025-03-08 Sat +2y>\n"
+        ));
+        assert!(text.contains(":EFFORT:   1d 2:30\n"));
+        assert!(text.contains("*** DONE [#D] Sharpen shears\n"));
+        assert!(import(&text, "other") == lists);
+    }
+
+    #[test]
+    fn notes_that_look_like_markup_round_trip() {
+        let notes = [
+            "DEADLINE: <2025-04-01 Tue> is only a note",
+            "* not a headline",
+            "# not a comment",
+            "#+END_EXAMPLE",
+            ":not_a_drawer:",
+            ",* keeps its comma",
+            "SCHEDULED: later",
+        ]
+        .join("\n");
+        let lists = vec![TaskList::new(
+            "Notes".to_string(),
+            Some(vec![Task {
+                title: "Odd notes".to_string(),
+                date: date(2),
+                notes,
+                ..Task::default()
+            }]),
+        )];
+        let text = export(&lists.iter().collect::<Vec<_>>());
+        assert!(text.contains("#+BEGIN_EXAMPLE\n"));
+        assert!(import(&text, "other") == lists);
+    }
+
+    #[test]
+    fn reads_other_org_files() {
+        let text = "\
+#+TITLE: Notes
+#+TODO: TODO REVIEW(r) | SHIPPED(s) DROPPED
+Loose text before any heading.
+* NEXT Loose task
+SCHEDULED: <2025-02-01 Sat 10:00 .+1d>
+* Projects
+** REVIEW [#A] Ship it :work:
+   CLOSED: [2025-02-02 Sun] DEADLINE: <2025-02-03 Mon -1d>
+   :LOGBOOK:
+   - State \"REVIEW\" from \"TODO\"
+   :END:
+   Body text.
+**** SHIPPED Deep child
+** Plain heading
+*** CANCELLED Old idea
+*bold* is not a heading
+";
+        let lists = import(text, "notes");
+        assert_eq!(lists.len(), 2);
+        assert_eq!(lists[0].name, "notes");
+        let loose = &lists[0].tasks[0];
+        assert_eq!(loose.title, "Loose task");
+        assert_eq!(loose.date, NaiveDate::from_ymd_opt(2025, 2, 1).unwrap());
+        assert_eq!(loose.recurrence, "daily".parse().ok());
+
+        let projects = &lists[1].tasks;
+        assert_eq!(projects.len(), 2);
+        let ship = &projects[0];
+        assert_eq!(ship.title, "Ship it");
+        assert!(ship.status == TaskStatus::NotStarted);
+        assert_eq!(ship.priority, Priority::Critical);
+        assert_eq!(ship.tags, ["work"]);
+        assert_eq!(ship.date, NaiveDate::from_ymd_opt(2025, 2, 3).unwrap());
+        assert_eq!(ship.notes, "Body text.");
+        assert_eq!(ship.sub_tasks[0].title, "Deep child");
+        assert!(ship.sub_tasks[0].status == TaskStatus::Finished);
+
+        let plain = &projects[1];
+        assert_eq!(plain.title, "Plain heading");
+        assert!(plain.sub_tasks[0].status == TaskStatus::Deleted);
+        assert_eq!(plain.sub_tasks[0].notes, "*bold* is not a heading");
+    }
+}
@@ -0,0 +1,268 @@
+use super::Imported;
+use crate::{
+    storage::format_error,
+    tabs::{Priority, Task, TaskCommandError, TaskList, TaskStatus, next_task_id},
+};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/*
+    The output of Taskwarrior's `task export`, a json array of tasks or
+    one task per line for older versions. The project of a task names its
+    list, depends becomes the tasks blocking it and annotations its notes.
+    Recurring templates are skipped since their pending instances are
+    exported as well. Every other field is listed in the summary.
+*/
+
+// kept by Taskwarrior for itself, nothing is lost by leaving them out
+const BOOKKEEPING: [&str; 8] = [
+    "id", "uuid", "entry", "modified", "end", "urgency", "mask", "imask",
+];
+
+// "20250115T120000Z", older versions also wrote epoch seconds
+fn parse_date(value: &Value) -> Option<NaiveDate> {
+    let text = match value {
+        Value::Number(seconds) => {
+            let time = DateTime::from_timestamp(seconds.as_i64()?, 0)?;
+            return Some(time.with_timezone(&Local).date_naive());
+        }
+        Value::String(text) => text,
+        _ => return None,
+    };
+    if let Ok(seconds) = text.parse::<i64>() {
+        return parse_date(&Value::from(seconds));
+    }
+    match NaiveDateTime::parse_from_str(text, "%Y%m%dT%H%M%SZ") {
+        Ok(time) => Some(time.and_utc().with_timezone(&Local).date_naive()),
+        Err(_) => NaiveDate::parse_from_str(text.get(..10)?, "%Y-%m-%d").ok(),
+    }
+}
+
+// uuids as an array, or comma separated in a string before Taskwarrior 2.6
+fn depends(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(uuids) => uuids
+            .iter()
+            .filter_map(|uuid| uuid.as_str().map(str::to_string))
+            .collect(),
+        Value::String(uuids) => uuids
+            .split(',')
+            .map(|uuid| uuid.trim().to_string())
+            .filter(|uuid| !uuid.is_empty())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn read_tasks(text: &str) -> Result<Vec<Map<String, Value>>, TaskCommandError> {
+    let values: Vec<Value> = match text.trim_start().starts_with('[') {
+        true => serde_json::from_str(text).map_err(format_error)?,
+        false => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .map_err(format_error)?,
+    };
+    values
+        .into_iter()
+        .map(|value| match value {
+            Value::Object(task) => Ok(task),
+            _ => Err(TaskCommandError::InvalidFileFormat {
+                line: 0,
+                column: 0,
+                message: "expected a list of tasks".to_string(),
+            }),
+        })
+        .collect()
+}
+
+fn plural(count: usize, what: &str) -> String {
+    match (count, what.strip_suffix('y')) {
+        (1, _) => format!("1 {what}"),
+        (count, Some(stem)) => format!("{count} {stem}ies"),
+        (count, None) => format!("{count} {what}s"),
+    }
+}
+
+pub fn import(text: &str, default_list: &str) -> Result<Imported, TaskCommandError> {
+    let today = Local::now().date_naive();
+    let mut lists: Vec<TaskList> = Vec::new();
+    let mut ids: HashMap<String, u64> = HashMap::new();
+    let mut blockers: Vec<(usize, usize, Vec<String>)> = Vec::new();
+    let mut skipped: BTreeMap<String, usize> = BTreeMap::new();
+    let mut templates = 0;
+    let mut imported = 0;
+
+    for fields in read_tasks(text)? {
+        if fields.get("status").and_then(Value::as_str) == Some("recurring") {
+            templates += 1;
+            continue;
+        }
+
+        let mut task = Task {
+            id: next_task_id(),
+            date: today,
+            ..Task::default()
+        };
+        let mut list = default_list.to_string();
+        let mut depends_on = Vec::new();
+        for (name, value) in &fields {
+            match name.as_str() {
+                "description" => task.title = value.as_str().unwrap_or_default().to_string(),
+                "status" => {
+                    task.status = match value.as_str() {
+                        Some("completed") => TaskStatus::Finished,
+                        Some("deleted") => TaskStatus::Deleted,
+                        _ => task.status.clone(),
+                    }
+                }
+                // started tasks are in progress unless their status says otherwise
+                "start" => {}
+                "due" => task.date = parse_date(value).unwrap_or(today),
+                "project" => {
+                    if let Some(project) = value.as_str().filter(|p| !p.is_empty()) {
+                        list = project.to_string();
+                    }
+                }
+                "tags" => {
+                    for tag in value.as_array().into_iter().flatten() {
+                        if let Some(tag) = tag.as_str() {
+                            task.add_tag(tag);
+                        }
+                    }
+                }
+                "priority" => {
+                    task.priority = match value.as_str() {
+                        Some("H") => Priority::High,
+                        Some("M") => Priority::Medium,
+                        Some("L") => Priority::Low,
+                        _ => Priority::None,
+                    }
+                }
+                "depends" => depends_on = depends(value),
+                "annotations" => {
+                    let notes: Vec<&str> = value
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|note| note.get("description")?.as_str())
+                        .collect();
+                    task.notes = notes.join("\n");
+                }
+                name if BOOKKEEPING.contains(&name) => {}
+                name => *skipped.entry(name.to_string()).or_default() += 1,
+            }
+        }
+        if fields.contains_key("start") && task.status == TaskStatus::NotStarted {
+            task.status = TaskStatus::InProgress;
+        }
+
+        if let Some(uuid) = fields.get("uuid").and_then(Value::as_str) {
+            ids.insert(uuid.to_string(), task.id);
+        }
+        let list_index = match lists.iter().position(|l| l.name == list) {
+            Some(index) => index,
+            None => {
+                lists.push(TaskList::new(list, None));
+                lists.len() - 1
+            }
+        };
+        if !depends_on.is_empty() {
+            blockers.push((list_index, lists[list_index].tasks.len(), depends_on));
+        }
+        lists[list_index].tasks.push(task);
+        imported += 1;
+    }
+
+    let mut missing = 0;
+    for (list, index, uuids) in blockers {
+        for uuid in uuids {
+            match ids.get(&uuid) {
+                Some(id) => lists[list].tasks[index].blocked_by.push(*id),
+                None => missing += 1,
+            }
+        }
+    }
+
+    let mut summary = vec![format!(
+        "Imported {} into {}.",
+        plural(imported, "task"),
+        plural(lists.len(), "list")
+    )];
+    if !skipped.is_empty() || templates > 0 || missing > 0 {
+        summary.push("Not brought over:".to_string());
+    }
+    for (name, count) in skipped {
+        summary.push(format!("  {name} on {}", plural(count, "task")));
+    }
+    if templates > 0 {
+        summary.push(format!("  {}", plural(templates, "recurring template")));
+    }
+    if missing > 0 {
+        summary.push(format!(
+            "  {} on tasks not in the file",
+            plural(missing, "dependency")
+        ));
+    }
+    Ok(Imported { lists, summary })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"[
+{"id":1,"description":"Fix the fence","due":"20250301T120000Z","entry":"20250101T100000Z","modified":"20250102T100000Z","priority":"H","project":"Home","status":"pending","start":"20250102T100000Z","tags":["garden","weekend"],"uuid":"aaaa","urgency":9.1,"estimate":"2h"},
+{"id":0,"description":"Buy paint","end":"20250105T100000Z","entry":"20250101T100000Z","project":"Home","status":"completed","uuid":"bbbb","annotations":[{"entry":"20250101T100000Z","description":"the green one"}]},
+{"id":2,"description":"Send invoice","depends":["aaaa","bbbb","cccc"],"entry":"20250101T100000Z","priority":"L","status":"pending","uuid":"dddd","wait":"20250201T000000Z","estimate":"1h"},
+{"id":0,"description":"Water plants","recur":"weekly","status":"recurring","uuid":"eeee"},
+{"id":0,"description":"Old idea","status":"deleted","uuid":"ffff","project":"Home"}
+]"#;
+
+    #[test]
+    fn imports_export() {
+        let Imported { lists, summary } = import(SAMPLE, "taskwarrior").unwrap();
+        let names: Vec<&str> = lists.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Home", "taskwarrior"]);
+
+        let home = &lists[0].tasks;
+        assert_eq!(home.len(), 3);
+        let fence = &home[0];
+        assert_eq!(fence.title, "Fix the fence");
+        assert!(fence.status == TaskStatus::InProgress);
+        assert_eq!(fence.priority, Priority::High);
+        assert_eq!(fence.tags, ["garden", "weekend"]);
+        assert!(home[1].status == TaskStatus::Finished);
+        assert_eq!(home[1].notes, "the green one");
+        assert!(home[2].status == TaskStatus::Deleted);
+
+        let invoice = &lists[1].tasks[0];
+        assert_eq!(invoice.priority, Priority::Low);
+        assert_eq!(invoice.blocked_by, [fence.id, home[1].id]);
+
+        assert_eq!(
+            summary,
+            [
+                "Imported 4 tasks into 2 lists.",
+                "Not brought over:",
+                "  estimate on 2 tasks",
+                "  wait on 1 task",
+                "  1 recurring template",
+                "  1 dependency on tasks not in the file",
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_one_task_per_line() {
+        let text = "{\"description\":\"a\",\"status\":\"pending\"}\n\
+            {\"description\":\"b\",\"status\":\"pending\",\"due\":\"1735732800\"}\n";
+        let Imported { lists, summary } = import(text, "tw").unwrap();
+        assert_eq!(plural(2, "dependency"), "2 dependencies");
+        assert_eq!(lists[0].tasks.len(), 2);
+        assert_eq!(summary, ["Imported 2 tasks into 1 list."]);
+        assert!(import("{nope", "tw").is_err());
+    }
+}
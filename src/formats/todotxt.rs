@@ -0,0 +1,434 @@
+use crate::{
+    recurrence::{Frequency, Recurrence},
+    tabs::{Priority, Task, TaskList, TaskStatus},
+};
+use chrono::{Local, NaiveDate};
+
+/*
+    todo.txt, one task per line:
+        x (A) 2024-05-01 call the bank +Home @phone due:2024-05-03
+    The first +project names the list of the task and @contexts become
+    tags. due:, pri:, rec: and status: are read into the task, other
+    key:value pairs stay part of the title. status:doing marks a task in
+    progress. Spaces in list names are written as underscores and read
+    back as spaces. Titles that start with something read as the x,
+    priority or dates get the project written in front of them, and title
+    words read as a project, context or one of those keys are written
+    with a backslash in front.
+
+    The file has no room for notes, durations, blockers or sub-tasks, so
+    sub-tasks are written as tasks of their own and the rest is dropped.
+*/
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const KEYS: [&str; 4] = ["due", "pri", "rec", "status"];
+const IN_PROGRESS: &str = "doing";
+
+fn priority_from_letter(letter: &str) -> Option<Priority> {
+    match letter {
+        "A" => Some(Priority::Critical),
+        "B" => Some(Priority::High),
+        "C" => Some(Priority::Medium),
+        // D to Z
+        letter if letter.len() == 1 && letter.bytes().all(|b| b.is_ascii_uppercase()) => {
+            Some(Priority::Low)
+        }
+        _ => None,
+    }
+}
+
+pub fn priority_letter(priority: Priority) -> Option<char> {
+    match priority {
+        Priority::None => None,
+        Priority::Low => Some('D'),
+        Priority::Medium => Some('C'),
+        Priority::High => Some('B'),
+        Priority::Critical => Some('A'),
+    }
+}
+
+// "(A)"
+fn parse_priority(word: &str) -> Option<Priority> {
+    priority_from_letter(word.strip_prefix('(')?.strip_suffix(')')?)
+}
+
+fn parse_date(word: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(word, DATE_FORMAT).ok()
+}
+
+// rec:1w, rec:+2m, rec:d, business days are not supported
+pub fn parse_recurrence(value: &str) -> Option<Recurrence> {
+    let value = value.strip_prefix('+').unwrap_or(value);
+    let unit = value.chars().last()?;
+    let count = &value[..value.len() - unit.len_utf8()];
+    let interval = match count {
+        "" => 1,
+        count => count.parse::<u32>().ok().filter(|n| *n > 0)?,
+    };
+    let frequency = match unit {
+        'd' => Frequency::Daily,
+        'w' => Frequency::Weekly(Vec::new()),
+        'm' => Frequency::Monthly(None),
+        'y' => Frequency::Yearly,
+        _ => return None,
+    };
+    Some(Recurrence {
+        frequency,
+        interval,
+        until: None,
+        anchor: None,
+    })
+}
+
+// only the plain every n days/weeks/months/years rules have a todo.txt form
+pub fn recurrence_value(recurrence: &Recurrence) -> Option<String> {
+    if recurrence.until.is_some() {
+        return None;
+    }
+    let unit = match recurrence.frequency {
+        Frequency::Daily => 'd',
+        Frequency::Weekly(ref days) if days.is_empty() => 'w',
+        Frequency::Monthly(None) => 'm',
+        Frequency::Yearly => 'y',
+        _ => return None,
+    };
+    Some(format!("{}{unit}", recurrence.interval))
+}
+
+// the list name and the task of one line, None for blank lines
+fn parse_line(line: &str) -> Option<(Option<String>, Task)> {
+    let mut words = line.split_whitespace().peekable();
+    words.peek()?;
+
+    let mut task = Task::default();
+    if words.next_if_eq(&"x").is_some() {
+        task.status = TaskStatus::Finished;
+    } else if let Some(priority) = words.peek().and_then(|word| parse_priority(word)) {
+        task.priority = priority;
+        words.next();
+    }
+    // completion date first on finished tasks, then the creation date
+    let date_count = if task.status == TaskStatus::Finished {
+        2
+    } else {
+        1
+    };
+    let mut dates = Vec::new();
+    while dates.len() < date_count
+        && let Some(date) = words.peek().and_then(|word| parse_date(word))
+    {
+        dates.push(date);
+        words.next();
+    }
+    match task.status {
+        TaskStatus::Finished => {
+            task.finished = dates.first().copied();
+            task.created = dates.get(1).copied();
+        }
+        _ => task.created = dates.first().copied(),
+    }
+
+    let mut list = None;
+    let mut due = None;
+    let mut title = Vec::new();
+    for word in words {
+        if let Some(escaped) = word.strip_prefix('\\') {
+            title.push(escaped);
+            continue;
+        }
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty())
+            && list.is_none()
+        {
+            list = Some(project.replace('_', " "));
+            continue;
+        }
+        if let Some(context) = word.strip_prefix('@').filter(|c| !c.is_empty()) {
+            task.add_tag(context);
+            continue;
+        }
+        let used = match word.split_once(':') {
+            Some(("due", value)) => parse_date(value).map(|date| due = Some(date)),
+            Some(("pri", value)) => {
+                priority_from_letter(value).map(|priority| task.priority = priority)
+            }
+            Some(("rec", value)) => {
+                parse_recurrence(value).map(|rule| task.recurrence = Some(rule))
+            }
+            Some(("status", IN_PROGRESS)) if task.status == TaskStatus::NotStarted => {
+                task.status = TaskStatus::InProgress;
+                Some(())
+            }
+            _ => None,
+        };
+        if used.is_none() {
+            title.push(word);
+        }
+    }
+
+    task.title = title.join(" ");
+    // there is a single date per task, the due date wins over the others
+    task.date = due
+        .or(dates.first().copied())
+        .unwrap_or_else(|| Local::now().date_naive());
+    Some((list, task))
+}
+
+// lists in the order their first task appears in the file
+pub fn import(text: &str, default_list: &str) -> Vec<TaskList> {
+    let mut lists: Vec<TaskList> = Vec::new();
+    for (list, task) in text.lines().filter_map(parse_line) {
+        let name = list.unwrap_or_else(|| default_list.to_string());
+        match lists.iter_mut().find(|l| l.name == name) {
+            Some(list) => list.tasks.push(task),
+            None => lists.push(TaskList::new(name, Some(vec![task]))),
+        }
+    }
+    lists
+}
+
+// a title word as written, so it is not read as anything else
+fn escape(word: &str) -> String {
+    let special = word.starts_with('\\')
+        || (word.len() > 1 && word.starts_with(['+', '@']))
+        || word
+            .split_once(':')
+            .is_some_and(|(key, _)| KEYS.contains(&key));
+    match special {
+        true => format!("\\{word}"),
+        false => word.to_string(),
+    }
+}
+
+fn format_line(list: &str, task: &Task) -> String {
+    let mut words = Vec::new();
+    let finished = task.status == TaskStatus::Finished;
+    let priority = priority_letter(task.priority);
+    if finished {
+        words.push("x".to_string());
+        // a creation date alone would be read as the completion date
+        if let Some(date) = task.finished {
+            words.push(date.format(DATE_FORMAT).to_string());
+            words.extend(
+                task.created
+                    .map(|date| date.format(DATE_FORMAT).to_string()),
+            );
+        }
+    } else {
+        words.extend(priority.map(|letter| format!("({letter})")));
+        words.extend(
+            task.created
+                .map(|date| date.format(DATE_FORMAT).to_string()),
+        );
+    }
+
+    let project = format!("+{}", list.split_whitespace().collect::<Vec<_>>().join("_"));
+    let guarded = task.title.split_whitespace().next().is_some_and(|word| {
+        word == "x" || parse_priority(word).is_some() || parse_date(word).is_some()
+    });
+    if guarded {
+        words.push(project.clone());
+    }
+    words.extend(task.title.split_whitespace().map(escape));
+    // finished tasks keep their priority as a tag, as other todo.txt tools do
+    if finished && let Some(letter) = priority {
+        words.push(format!("pri:{letter}"));
+    }
+    if !guarded {
+        words.push(project);
+    }
+    if task.status == TaskStatus::InProgress {
+        words.push(format!("status:{IN_PROGRESS}"));
+    }
+    for tag in &task.tags {
+        words.push(format!("@{tag}"));
+    }
+    words.push(format!("due:{}", task.date.format(DATE_FORMAT)));
+    if let Some(value) = task.recurrence.as_ref().and_then(recurrence_value) {
+        words.push(format!("rec:{value}"));
+    }
+    words.join(" ")
+}
+
+pub fn export(task_lists: &[&TaskList]) -> String {
+    fn write_tasks(list: &str, tasks: &[Task], text: &mut String) {
+        for task in tasks {
+            if task.status != TaskStatus::Deleted {
+                text.push_str(&format_line(list, task));
+                text.push('\n');
+            }
+            write_tasks(list, &task.sub_tasks, text);
+        }
+    }
+
+    let mut text = String::new();
+    for list in task_lists {
+        write_tasks(&list.name, &list.tasks, &mut text);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+(A) Call Mom +Family @phone due:2024-05-03
+x 2024-05-02 2024-04-28 Pay rent +Home @bank pri:B
+(C) 2024-04-30 Water plants +Home rec:1w
+Review pull requests +Work_Stuff @laptop @office url:https://example.com
+Buy milk
+
+x Return library books due:2024-05-01
+";
+
+    fn date(text: &str) -> NaiveDate {
+        parse_date(text).unwrap()
+    }
+
+    #[test]
+    fn imports_sample() {
+        let lists = import(SAMPLE, "todo");
+        let names: Vec<&str> = lists.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Family", "Home", "Work Stuff", "todo"]);
+
+        let call = &lists[0].tasks[0];
+        assert_eq!(call.title, "Call Mom");
+        assert_eq!(call.priority, Priority::Critical);
+        assert_eq!(call.tags, ["phone"]);
+        assert_eq!(call.date, date("2024-05-03"));
+        assert!(call.status == TaskStatus::NotStarted);
+
+        let rent = &lists[1].tasks[0];
+        assert_eq!(rent.title, "Pay rent");
+        assert!(rent.status == TaskStatus::Finished);
+        assert_eq!(rent.priority, Priority::High);
+        // the completion date is the only date it has
+        assert_eq!(rent.date, date("2024-05-02"));
+
+        let plants = &lists[1].tasks[1];
+        assert_eq!(plants.priority, Priority::Medium);
+        assert_eq!(plants.date, date("2024-04-30"));
+        assert_eq!(plants.recurrence, "weekly".parse().ok());
+
+        let review = &lists[2].tasks[0];
+        assert_eq!(review.title, "Review pull requests url:https://example.com");
+        assert_eq!(review.tags, ["laptop", "office"]);
+
+        let unfiled: Vec<&str> = lists[3].tasks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(unfiled, ["Buy milk", "Return library books"]);
+        assert!(lists[3].tasks[1].status == TaskStatus::Finished);
+    }
+
+    #[test]
+    fn sample_round_trips() {
+        let imported = import(SAMPLE, "todo");
+        let exported = export(&imported.iter().collect::<Vec<_>>());
+        assert!(import(&exported, "other") == imported);
+        // writing it again gives the same text
+        let again = import(&exported, "other");
+        assert_eq!(export(&again.iter().collect::<Vec<_>>()), exported);
+    }
+
+    #[test]
+    fn tasks_round_trip() {
+        let task = |title: &str| Task {
+            title: title.to_string(),
+            date: date("2025-01-15"),
+            ..Task::default()
+        };
+        let lists = vec![
+            TaskList::new(
+                "Side Project".to_string(),
+                Some(vec![
+                    Task {
+                        priority: Priority::Low,
+                        tags: vec!["home".to_string(), "evening".to_string()],
+                        recurrence: "monthly/3".parse().ok(),
+                        ..task("Write docs")
+                    },
+                    Task {
+                        status: TaskStatus::Finished,
+                        priority: Priority::Critical,
+                        ..task("Ship it")
+                    },
+                ]),
+            ),
+            TaskList::new("Errands".to_string(), Some(vec![task("Post office")])),
+        ];
+        let text = export(&lists.iter().collect::<Vec<_>>());
+        assert!(import(&text, "todo") == lists);
+    }
+
+    #[test]
+    fn dates_and_leading_tokens_round_trip() {
+        let task = |title: &str| Task {
+            title: title.to_string(),
+            date: date("2025-01-15"),
+            ..Task::default()
+        };
+        let tasks = vec![
+            Task {
+                status: TaskStatus::Finished,
+                finished: Some(date("2025-01-10")),
+                created: Some(date("2025-01-02")),
+                ..task("Pay rent")
+            },
+            Task {
+                created: Some(date("2025-01-03")),
+                ..task("x marks the spot")
+            },
+            task("(B) is not a priority"),
+            Task {
+                priority: Priority::High,
+                ..task("2024-12-31 is not a date")
+            },
+            Task {
+                status: TaskStatus::Finished,
+                ..task("2024-12-31 either")
+            },
+        ];
+        let lists = vec![TaskList::new("Tasks".to_string(), Some(tasks))];
+        let text = export(&lists.iter().collect::<Vec<_>>());
+        assert!(text.starts_with("x 2025-01-10 2025-01-02 Pay rent "));
+        assert!(import(&text, "todo") == lists);
+    }
+
+    #[test]
+    fn title_words_and_status_round_trip() {
+        let task = |title: &str| Task {
+            title: title.to_string(),
+            date: date("2025-01-15"),
+            ..Task::default()
+        };
+        let tasks = vec![
+            Task {
+                status: TaskStatus::InProgress,
+                ..task("Email +ops about a:b")
+            },
+            task("+1 for @team due:friday pri:A"),
+            task("status:doing is \\+escaped"),
+        ];
+        let lists = vec![TaskList::new("Tasks".to_string(), Some(tasks))];
+        let text = export(&lists.iter().collect::<Vec<_>>());
+        assert!(text.starts_with("Email \\+ops about a:b +Tasks status:doing "));
+        assert!(import(&text, "todo") == lists);
+    }
+
+    #[test]
+    fn sub_tasks_are_flattened() {
+        let list = TaskList::new(
+            "Tasks".to_string(),
+            Some(vec![Task {
+                title: "Parent".to_string(),
+                sub_tasks: vec![Task {
+                    title: "Child".to_string(),
+                    ..Task::default()
+                }],
+                ..Task::default()
+            }]),
+        );
+        let lists = import(&export(&[&list]), "todo");
+        let titles: Vec<&str> = lists[0].tasks.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["Parent", "Child"]);
+    }
+}
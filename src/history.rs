@@ -0,0 +1,144 @@
+use crate::tabs::TaskList;
+use std::rc::Rc;
+
+/*
+    Undo and redo stacks of whole task list snapshots. A snapshot is taken
+    right before every change so undoing swaps the current lists with the
+    last one taken.
+
+    Every change also gets a new revision number, which is what tells
+    whether the lists still match the data file. Snapshots keep the
+    revision they had so undoing back to a saved state counts as saved,
+    unless a change that cannot be undone was made since.
+*/
+
+pub struct History {
+    undo: Vec<(u64, Rc<Vec<TaskList>>)>,
+    redo: Vec<(u64, Rc<Vec<TaskList>>)>,
+    // number of changes that can be undone, 0 disables the history
+    depth: usize,
+    revision: u64,
+    // never handed out twice, unlike revision which goes back on undo
+    last_revision: u64,
+    // revision of the last change that cannot be undone
+    kept: u64,
+}
+
+impl History {
+    pub fn new(depth: usize) -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            depth,
+            revision: 0,
+            last_revision: 0,
+            kept: 0,
+        }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    // counts a change that cannot be undone, like one to the settings
+    pub fn changed(&mut self) {
+        self.next_revision();
+        self.kept = self.revision;
+    }
+
+    fn next_revision(&mut self) {
+        self.last_revision += 1;
+        self.revision = self.last_revision;
+    }
+
+    // a snapshot from before the last change that cannot be undone is
+    // restored as a new state, it never matched the data file that way
+    fn restore(&mut self, revision: u64) -> u64 {
+        match revision < self.kept {
+            true => {
+                let old = self.revision;
+                self.next_revision();
+                old
+            }
+            false => std::mem::replace(&mut self.revision, revision),
+        }
+    }
+
+    // records a snapshot taken before a change that might not have happened
+    pub fn push(&mut self, snapshot: Rc<Vec<TaskList>>) {
+        let revision = self.revision;
+        self.next_revision();
+        if self.depth == 0 {
+            return;
+        }
+        self.undo.push((revision, snapshot));
+        if self.undo.len() > self.depth {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self, task_lists: &mut Vec<TaskList>) -> bool {
+        match self.undo.pop() {
+            Some((revision, snapshot)) => {
+                let revision = self.restore(revision);
+                self.redo.push((
+                    revision,
+                    Rc::new(std::mem::replace(task_lists, Rc::unwrap_or_clone(snapshot))),
+                ));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self, task_lists: &mut Vec<TaskList>) -> bool {
+        match self.redo.pop() {
+            Some((revision, snapshot)) => {
+                let revision = self.restore(revision);
+                self.undo.push((
+                    revision,
+                    Rc::new(std::mem::replace(task_lists, Rc::unwrap_or_clone(snapshot))),
+                ));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        if self.undo.len() > depth {
+            self.undo.drain(..self.undo.len() - depth);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_past_a_settings_change_is_not_saved() {
+        let mut history = History::new(10);
+        let mut task_lists = vec![TaskList::new("Tasks".to_string(), None)];
+        let saved = history.revision();
+
+        history.push(Rc::new(task_lists.clone()));
+        task_lists.push(TaskList::new("Later".to_string(), None));
+        history.changed();
+        assert!(history.undo(&mut task_lists));
+        assert_ne!(history.revision(), saved);
+
+        // without the settings change undo gets back to the saved state
+        let mut history = History::new(10);
+        history.push(Rc::new(task_lists.clone()));
+        assert!(history.undo(&mut task_lists));
+        assert_eq!(history.revision(), saved);
+    }
+}
@@ -0,0 +1,644 @@
+use crate::{
+    storage,
+    tabs::{Task, TaskCommandError, TaskList, TaskStatus, find_task, remove_task, task_by_id_mut},
+};
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/*
+    Append-only record of every change made to the task lists, one json
+    entry per line in <data file>.journal. The lists are noted before each
+    change, the same as for undo, and the difference to the previous note
+    becomes the entries of that change. They are kept until the next save
+    and only written then, so replaying the journal from the top rebuilds
+    the saved lists. Once more than COMPACT_AFTER entries are older than
+    KEEP_DAYS they are folded into a single snapshot.
+*/
+
+const COMPACT_AFTER: usize = 500;
+const KEEP_DAYS: i64 = 30;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    // the whole state, the journal starts with one
+    Snapshot {
+        lists: Vec<TaskList>,
+    },
+    // names of the lists in order, lists left out are dropped
+    Lists {
+        names: Vec<String>,
+    },
+    // tasks are written without their sub-tasks, which get events of their own
+    Created {
+        list: usize,
+        parent: Option<u64>,
+        index: usize,
+        task: Task,
+    },
+    Edited {
+        task: Task,
+    },
+    StatusChanged {
+        id: u64,
+        from: TaskStatus,
+        to: TaskStatus,
+    },
+    Moved {
+        id: u64,
+        list: usize,
+        parent: Option<u64>,
+        index: usize,
+    },
+    Deleted {
+        id: u64,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Local>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+fn shallow(task: &Task) -> Task {
+    Task {
+        sub_tasks: Vec::new(),
+        ..task.clone()
+    }
+}
+
+fn insert(task_lists: &mut [TaskList], list: usize, parent: Option<u64>, index: usize, task: Task) {
+    if let Some(parent) = parent
+        && let Some(parent) = task_by_id_mut(task_lists, parent)
+    {
+        let index = index.min(parent.sub_tasks.len());
+        parent.sub_tasks.insert(index, task);
+        return;
+    }
+    if let Some(list) = task_lists.get_mut(list) {
+        list.tasks.insert(index.min(list.tasks.len()), task);
+    }
+}
+
+pub fn apply(task_lists: &mut Vec<TaskList>, event: &Event) {
+    match event {
+        Event::Snapshot { lists } => *task_lists = lists.clone(),
+        Event::Lists { names } => {
+            let mut old = std::mem::take(task_lists);
+            *task_lists = names
+                .iter()
+                .map(
+                    |name| match old.iter().position(|list| &list.name == name) {
+                        Some(index) => old.remove(index),
+                        None => TaskList::new(name.clone(), None),
+                    },
+                )
+                .collect();
+        }
+        Event::Created {
+            list,
+            parent,
+            index,
+            task,
+        } => {
+            remove_task(task_lists, task.id);
+            insert(task_lists, *list, *parent, *index, task.clone());
+        }
+        Event::Edited { task } => {
+            if let Some(current) = task_by_id_mut(task_lists, task.id) {
+                let sub_tasks = std::mem::take(&mut current.sub_tasks);
+                *current = Task {
+                    sub_tasks,
+                    ..task.clone()
+                };
+            }
+        }
+        Event::StatusChanged { id, to, .. } => {
+            if let Some(task) = task_by_id_mut(task_lists, *id) {
+                task.status = to.clone();
+            }
+        }
+        Event::Moved {
+            id,
+            list,
+            parent,
+            index,
+        } => {
+            if let Some(task) = remove_task(task_lists, *id) {
+                insert(task_lists, *list, *parent, *index, task);
+            }
+        }
+        Event::Deleted { id } => {
+            remove_task(task_lists, *id);
+        }
+    }
+}
+
+pub fn replay(entries: &[Entry]) -> Vec<TaskList> {
+    let mut task_lists = Vec::new();
+    for entry in entries {
+        apply(&mut task_lists, &entry.event);
+    }
+    task_lists
+}
+
+/*
+    The events that turn from into to. Both sides are indexed by id once,
+    then the tasks of every spot in to, a list or the sub-tasks of a task,
+    are compared with what the events so far left there. Only the tasks out
+    of place get an event, so a single change costs about one pass.
+*/
+pub fn diff(from: &[TaskList], to: &[TaskList]) -> Vec<Event> {
+    #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+    enum Place {
+        List(usize),
+        Task(u64),
+    }
+
+    // every spot of to, each before the spots below it
+    fn spots<'a>(
+        list: usize,
+        place: Place,
+        tasks: &'a [Task],
+        out: &mut Vec<(usize, Place, &'a [Task])>,
+    ) {
+        out.push((list, place, tasks));
+        for task in tasks {
+            spots(list, Place::Task(task.id), &task.sub_tasks, out);
+        }
+    }
+
+    // a deleted task takes its sub-tasks with it
+    fn deletes(tasks: &[Task], kept: &HashMap<u64, &Task>, events: &mut Vec<Event>) {
+        for task in tasks {
+            match kept.contains_key(&task.id) {
+                true => deletes(&task.sub_tasks, kept, events),
+                false => events.push(Event::Deleted { id: task.id }),
+            }
+        }
+    }
+
+    // the tasks still there after the deletes, by spot
+    fn fill<'a>(
+        place: Place,
+        tasks: &'a [Task],
+        kept: &HashMap<u64, &Task>,
+        working: &mut HashMap<Place, Vec<u64>>,
+        old: &mut HashMap<u64, (Place, &'a Task)>,
+    ) {
+        for task in tasks.iter().filter(|task| kept.contains_key(&task.id)) {
+            working.entry(place).or_default().push(task.id);
+            old.insert(task.id, (place, task));
+            fill(Place::Task(task.id), &task.sub_tasks, kept, working, old);
+        }
+    }
+
+    let mut spots_to = Vec::new();
+    for (list, task_list) in to.iter().enumerate() {
+        spots(list, Place::List(list), &task_list.tasks, &mut spots_to);
+    }
+    let kept: HashMap<u64, &Task> = spots_to
+        .iter()
+        .flat_map(|(_, _, tasks)| tasks.iter())
+        .map(|task| (task.id, task))
+        .collect();
+
+    let mut events = Vec::new();
+    for list in from {
+        deletes(&list.tasks, &kept, &mut events);
+    }
+
+    // lists are matched by name as apply does, dropped ones take their tasks
+    let names: Vec<String> = to.iter().map(|list| list.name.clone()).collect();
+    let mut lists: Vec<Option<usize>> = (0..from.len()).map(Some).collect();
+    if from.iter().map(|list| &list.name).ne(names.iter()) {
+        let mut left: Vec<usize> = (0..from.len()).collect();
+        lists = vec![None; from.len()];
+        for (index, name) in names.iter().enumerate() {
+            if let Some(position) = left.iter().position(|list| &from[*list].name == name) {
+                lists[left.remove(position)] = Some(index);
+            }
+        }
+        events.push(Event::Lists { names });
+    }
+
+    let mut working = HashMap::new();
+    let mut old = HashMap::new();
+    for (task_list, list) in from.iter().zip(lists) {
+        if let Some(list) = list {
+            fill(
+                Place::List(list),
+                &task_list.tasks,
+                &kept,
+                &mut working,
+                &mut old,
+            );
+        }
+    }
+
+    // tasks that stay are edited where they are, before any moves
+    for task in spots_to.iter().flat_map(|(_, _, tasks)| tasks.iter()) {
+        let Some((_, before)) = old.get(&task.id) else {
+            continue;
+        };
+        if before.status != task.status {
+            events.push(Event::StatusChanged {
+                id: task.id,
+                from: before.status.clone(),
+                to: task.status.clone(),
+            });
+        }
+        if !before.same_details(task) {
+            events.push(Event::Edited {
+                task: shallow(task),
+            });
+        }
+    }
+
+    let mut places: HashMap<u64, Place> = old
+        .into_iter()
+        .map(|(id, (place, _))| (id, place))
+        .collect();
+    for (list, place, tasks) in spots_to {
+        let parent = match place {
+            Place::List(_) => None,
+            Place::Task(id) => Some(id),
+        };
+        for (index, task) in tasks.iter().enumerate() {
+            if working.get(&place).and_then(|ids| ids.get(index)) == Some(&task.id) {
+                continue;
+            }
+            let event = match places.insert(task.id, place) {
+                Some(before) => {
+                    if let Some(ids) = working.get_mut(&before) {
+                        ids.retain(|id| *id != task.id);
+                    }
+                    Event::Moved {
+                        id: task.id,
+                        list,
+                        parent,
+                        index,
+                    }
+                }
+                None => Event::Created {
+                    list,
+                    parent,
+                    index,
+                    task: shallow(task),
+                },
+            };
+            working.entry(place).or_default().insert(index, task.id);
+            events.push(event);
+        }
+    }
+    events
+}
+
+fn describe(task_lists: &[TaskList], event: &Event) -> Option<String> {
+    let title = |id: u64| match find_task(task_lists, id) {
+        Some((list, path)) => task_lists[list]
+            .get_task(&path)
+            .map_or(format!("#{id}"), |task| format!("\"{}\"", task.title)),
+        None => format!("#{id}"),
+    };
+    let list_name = |list: usize| task_lists.get(list).map_or("?", |l| l.name.as_str());
+
+    Some(match event {
+        Event::Snapshot { .. } => return None,
+        Event::Lists { names } => format!("lists are now {}", names.join(", ")),
+        Event::Created { list, task, .. } => {
+            format!("created \"{}\" in {}", task.title, list_name(*list))
+        }
+        Event::Edited { task } => format!("edited \"{}\"", task.title),
+        Event::StatusChanged { id, from, to } => {
+            format!("{} {} -> {}", title(*id), from.get_name(), to.get_name())
+        }
+        Event::Moved { id, list, .. } => format!("moved {} in {}", title(*id), list_name(*list)),
+        Event::Deleted { id } => format!("deleted {}", title(*id)),
+    })
+}
+
+pub fn start_of_day(date: NaiveDate) -> Option<DateTime<Local>> {
+    date.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
+}
+
+// one line per change made at or after since, oldest first
+pub fn changes_since(entries: &[Entry], since: DateTime<Local>) -> Vec<String> {
+    let mut task_lists = Vec::new();
+    let mut lines = Vec::new();
+    for entry in entries {
+        if entry.time >= since
+            && let Some(text) = describe(&task_lists, &entry.event)
+        {
+            lines.push(format!("{} {text}", entry.time.format("%m-%d %H:%M")));
+        }
+        apply(&mut task_lists, &entry.event);
+    }
+    lines
+}
+
+pub fn journal_path(data_file: &str) -> PathBuf {
+    let mut name = Path::new(data_file).as_os_str().to_owned();
+    name.push(".journal");
+    PathBuf::from(name)
+}
+
+// every entry of a journal, none when there is no journal yet
+pub fn read(path: &Path) -> Result<Vec<Entry>, TaskCommandError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(storage::io_error(e)),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line).map_err(|e| TaskCommandError::InvalidFileFormat {
+                line: number + 1,
+                column: e.column(),
+                message: format!("journal: {e}"),
+            })
+        })
+        .collect()
+}
+
+fn to_lines(entries: &[Entry]) -> Result<String, TaskCommandError> {
+    let mut text = String::new();
+    for entry in entries {
+        text += &serde_json::to_string(entry).map_err(|e| TaskCommandError::Io(e.to_string()))?;
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+pub struct Journal {
+    path: PathBuf,
+    // the lists as the journal describes them, None until it was read
+    state: Option<Vec<TaskList>>,
+    // size of the file after the last read or write, to notice other writers
+    len: u64,
+    // the lists before the first change that is not written yet
+    base: Option<Rc<Vec<TaskList>>>,
+    // entries of the changes made since the last save
+    pending: Vec<Entry>,
+    // the lists at the last note and when it was taken
+    last: Option<(DateTime<Local>, Rc<Vec<TaskList>>)>,
+}
+
+impl Journal {
+    pub fn new(data_file: &str) -> Journal {
+        Journal {
+            path: journal_path(data_file),
+            state: None,
+            len: 0,
+            base: None,
+            pending: Vec::new(),
+            last: None,
+        }
+    }
+
+    // called with the lists before each change, turns the change made since
+    // the previous call into entries dated when that change was started
+    pub fn note(&mut self, task_lists: Rc<Vec<TaskList>>) {
+        match self.last.take() {
+            Some((time, last)) => {
+                let events = diff(&last, &task_lists);
+                self.pending
+                    .extend(events.into_iter().map(|event| Entry { time, event }));
+                self.base.get_or_insert(last);
+            }
+            None => {
+                self.base.get_or_insert_with(|| Rc::clone(&task_lists));
+            }
+        }
+        self.last = Some((Local::now(), task_lists));
+    }
+
+    // drops the unsaved changes, for when the lists were read from the disk
+    pub fn reset(&mut self, task_lists: &[TaskList]) {
+        self.base = None;
+        self.pending.clear();
+        self.last = Some((Local::now(), Rc::new(task_lists.to_vec())));
+    }
+
+    fn file_len(&self) -> u64 {
+        fs::metadata(&self.path).map_or(0, |m| m.len())
+    }
+
+    // appends the changes noted since the last save, task_lists being saved
+    pub fn record(&mut self, task_lists: &[TaskList]) -> Result<(), TaskCommandError> {
+        self.note(Rc::new(task_lists.to_vec()));
+        if self.state.is_none() || self.file_len() != self.len {
+            let entries = read(&self.path)?;
+            let cutoff = Local::now() - TimeDelta::days(KEEP_DAYS);
+            if entries.iter().skip(1).filter(|e| e.time < cutoff).count() > COMPACT_AFTER {
+                self.compact(cutoff)?;
+            }
+            self.state = (!entries.is_empty()).then(|| replay(&entries));
+        }
+        let base = self
+            .base
+            .take()
+            .unwrap_or_else(|| Rc::new(task_lists.to_vec()));
+        let pending = std::mem::take(&mut self.pending);
+
+        // whatever the journal is missing from before the first change,
+        // e.g. when another program wrote the data file
+        let events = match &self.state {
+            None => vec![Event::Snapshot {
+                lists: Rc::unwrap_or_clone(base),
+            }],
+            Some(state) => diff(state, &base),
+        };
+        let time = pending.first().map_or_else(Local::now, |entry| entry.time);
+        let mut entries: Vec<Entry> = events
+            .into_iter()
+            .map(|event| Entry { time, event })
+            .collect();
+        entries.extend(pending);
+        if !entries.is_empty() {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .map_err(storage::io_error)?;
+            file.write_all(to_lines(&entries)?.as_bytes())
+                .map_err(storage::io_error)?;
+        }
+        self.state = Some(task_lists.to_vec());
+        self.len = self.file_len();
+        Ok(())
+    }
+
+    // folds every entry older than cutoff into a single snapshot
+    pub fn compact(&mut self, cutoff: DateTime<Local>) -> Result<(), TaskCommandError> {
+        let entries = read(&self.path)?;
+        let split = entries.partition_point(|entry| entry.time < cutoff);
+        if split <= 1 {
+            return Ok(());
+        }
+        let snapshot = Entry {
+            time: entries[split - 1].time,
+            event: Event::Snapshot {
+                lists: replay(&entries[..split]),
+            },
+        };
+        let mut kept = vec![snapshot];
+        kept.extend_from_slice(&entries[split..]);
+        storage::write_file(&self.path.to_string_lossy(), to_lines(&kept)?.as_bytes())?;
+        self.len = self.file_len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: u64, title: &str, sub_tasks: Vec<Task>) -> Task {
+        Task {
+            id,
+            title: title.to_string(),
+            sub_tasks,
+            ..Task::default()
+        }
+    }
+
+    fn list(name: &str, tasks: Vec<Task>) -> TaskList {
+        TaskList::new(name.to_string(), Some(tasks))
+    }
+
+    // applying the diff to from gives to, and so does replaying it
+    fn round_trip(from: &[TaskList], to: &[TaskList]) {
+        let events = diff(from, to);
+        let mut applied = from.to_vec();
+        for event in &events {
+            apply(&mut applied, event);
+        }
+        assert!(applied == to);
+
+        let time = Local::now();
+        let mut entries = vec![Entry {
+            time,
+            event: Event::Snapshot {
+                lists: from.to_vec(),
+            },
+        }];
+        entries.extend(events.into_iter().map(|event| Entry { time, event }));
+        assert!(replay(&entries) == to);
+    }
+
+    #[test]
+    fn moves_between_parents_and_lists() {
+        let from = vec![
+            list(
+                "Home",
+                vec![
+                    task(1, "a", vec![task(2, "b", vec![])]),
+                    task(3, "c", vec![]),
+                ],
+            ),
+            list("Work", vec![task(4, "d", vec![])]),
+        ];
+        let to = vec![
+            list(
+                "Home",
+                vec![task(3, "c", vec![task(1, "a edited", vec![])])],
+            ),
+            list("Work", vec![task(2, "b", vec![]), task(4, "d", vec![])]),
+        ];
+        round_trip(&from, &to);
+    }
+
+    #[test]
+    fn lists_renamed_added_and_dropped() {
+        let from = vec![
+            list("Home", vec![task(1, "a", vec![])]),
+            list("Work", vec![task(2, "b", vec![])]),
+        ];
+        let to = vec![
+            list("Later", vec![task(2, "b", vec![])]),
+            list("House", vec![task(1, "a", vec![task(5, "new", vec![])])]),
+        ];
+        round_trip(&from, &to);
+    }
+
+    #[test]
+    fn nested_deletes() {
+        let from = vec![list(
+            "Tasks",
+            vec![
+                task(1, "a", vec![task(2, "b", vec![task(3, "c", vec![])])]),
+                task(4, "d", vec![task(5, "e", vec![]), task(6, "f", vec![])]),
+            ],
+        )];
+        let mut finished = task(6, "f", vec![]);
+        finished.status = TaskStatus::Finished;
+        let to = vec![list("Tasks", vec![task(4, "d", vec![finished])])];
+        round_trip(&from, &to);
+    }
+
+    #[test]
+    fn sorting_moves_siblings() {
+        let tasks = |ids: &[u64]| {
+            ids.iter()
+                .map(|id| task(*id, &id.to_string(), vec![]))
+                .collect()
+        };
+        let from = vec![list("Tasks", tasks(&[1, 2, 3, 4, 5]))];
+        let to = vec![list("Tasks", tasks(&[5, 3, 1, 4, 2]))];
+        round_trip(&from, &to);
+    }
+
+    #[test]
+    fn one_change_gives_one_event() {
+        let many: Vec<Task> = (1..=2000).map(|id| task(id, "same", vec![])).collect();
+        let from = vec![list("Tasks", many.clone())];
+        let mut to = vec![list("Tasks", many)];
+        to[0].tasks.insert(0, task(9999, "new", vec![]));
+        let events = diff(&from, &to);
+        assert!(matches!(events[..], [Event::Created { index: 0, .. }]));
+        round_trip(&from, &to);
+    }
+
+    #[test]
+    fn every_change_between_saves_is_kept() {
+        let path = std::env::temp_dir().join(format!("todoapp-journal-{}", std::process::id()));
+        let data_file = path.to_string_lossy().into_owned();
+        let _ = fs::remove_file(journal_path(&data_file));
+
+        let empty = vec![list("Tasks", vec![])];
+        let added = vec![list("Tasks", vec![task(1, "short lived", vec![])])];
+        let mut journal = Journal::new(&data_file);
+        journal.reset(&empty);
+        journal.note(Rc::new(empty.clone()));
+        journal.note(Rc::new(added));
+        journal.record(&empty).unwrap();
+
+        let entries = read(&journal_path(&data_file)).unwrap();
+        let events: Vec<&str> = entries
+            .iter()
+            .map(|entry| match entry.event {
+                Event::Snapshot { .. } => "snapshot",
+                Event::Created { .. } => "created",
+                Event::Deleted { .. } => "deleted",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(events, ["snapshot", "created", "deleted"]);
+        assert!(replay(&entries) == empty);
+        fs::remove_file(journal_path(&data_file)).unwrap();
+    }
+}
@@ -1,18 +1,28 @@
-use crate::{popup::*, tabs::*, theme::THEME, widgets::TextEntry};
-use crossterm::event::{self, KeyCode};
+use crate::{history::History, popup::*, tabs::*, theme::THEME, widgets::TextEntry};
+use crossterm::event::{self, KeyCode, KeyModifiers};
 use ratatui::{
     prelude::*,
     widgets::{Block, Widget},
 };
 use std::{
     io::{self},
+    process::ExitCode,
     time::Instant,
 };
 
+mod cli;
+mod dependencies;
+mod formats;
+mod history;
+mod journal;
+mod merge;
 mod popup;
+mod recurrence;
+mod storage;
 mod tabs;
 mod theme;
 mod tui;
+mod watcher;
 mod widgets;
 
 pub enum CommandRequest {
@@ -40,16 +50,34 @@ pub struct App {
 
     command_field: TextEntry,
     error_str: String,
+    error_style: Style,
     frames_since_error: Option<u32>,
 
     task_lists: Vec<TaskList>,
-    task_lists_backup: Vec<TaskList>,
     options: Options,
+    last_input: Instant,
+    // set when an autosave failed, cleared by the next key press
+    autosave_paused: bool,
+    // the data file changed on disk and the user put off deciding what to do
+    conflict_pending: bool,
+    quit_after_conflict: bool,
+    // None when the data file cannot be watched, it is then only read at startup
+    watcher: Option<watcher::FileWatcher>,
+    // set by the watcher, handled once nothing is being edited
+    disk_changed: bool,
+    // why the data file could not be locked, when it was not held elsewhere
+    lock_error: Option<TaskCommandError>,
 
     task_list_tab: TaskListTab,
     calendar_tab: CalendarTab,
     options_tab: OptionsTab,
     save_window: ConfirmationPopup,
+    // errors too long for the bottom bar
+    message_window: ChoicePopup,
+    // offered when the data file could not be loaded at startup
+    recovery_window: ChoicePopup,
+    // offered when saving would overwrite changes made by another program
+    conflict_window: ChoicePopup,
 }
 
 impl Widget for &App {
@@ -68,13 +96,22 @@ impl Widget for &App {
         self.render_title_bar(title_bar, buf);
         match self.current_tab {
             Tab::TaskList => self.task_list_tab.render(canvas, buf, &self.task_lists),
-            Tab::Calendar => self.calendar_tab.render(canvas, buf),
+            Tab::Calendar => self.calendar_tab.render(canvas, buf, &self.task_lists),
             Tab::Options => self.options_tab.render(canvas, buf, &self.options),
         }
 
         if self.save_window.status == PopupStatus::InUse {
             self.save_window.render(area, buf);
         }
+        if self.recovery_window.status == PopupStatus::InUse {
+            self.recovery_window.render(area, buf);
+        }
+        if self.conflict_window.status == PopupStatus::InUse {
+            self.conflict_window.render(area, buf);
+        }
+        if self.message_window.status == PopupStatus::InUse {
+            self.message_window.render(area, buf);
+        }
 
         if self.mode == RunningMode::Command {
             Line::from(vec![
@@ -87,9 +124,9 @@ impl Widget for &App {
                 bottom_bar.y,
             )]
                 .set_style(THEME.root_cursor);
-        } else if let Some(_) = self.frames_since_error {
-            Span::from(format!("Error: {}", self.error_str))
-                .style(THEME.command_error)
+        } else if self.frames_since_error.is_some() {
+            Span::from(self.error_str.as_str())
+                .style(self.error_style)
                 .render(bottom_bar, buf);
         } else {
             self.render_bottom_bar(bottom_bar, buf);
@@ -106,15 +143,35 @@ impl Widget for &App {
 impl App {
     pub fn run(&mut self, terminal: &mut tui::Tui) -> io::Result<()> {
         // initialization
-        self.command_field.set_text("t load".to_string());
-        self.process_command();
-        self.task_lists_backup = self.task_lists.clone();
-        self.task_lists_backup.sort();
+        if storage::data_exists(&self.task_list_tab.data_file) {
+            self.load_on_start();
+        } else {
+            // first run, the file is created on the first save
+            self.task_lists = vec![TaskList::new("Tasks".to_string(), None)];
+            self.task_list_tab.mark_saved(&self.task_lists, None);
+            self.post_info(format!(
+                "No tasks yet, they will be saved to {}",
+                self.task_list_tab.data_file
+            ));
+        }
+        if let Some(e) = self.lock_error.take() {
+            self.post_error(format!(
+                "Could not lock {} ({e}), changes will not be saved",
+                self.task_list_tab.data_file
+            ));
+        } else if self.task_list_tab.lock.is_none() {
+            self.post_info(format!(
+                "{} is open in another window, changes will not be saved",
+                self.task_list_tab.data_file
+            ));
+        }
 
         // main loop
         while self.mode != RunningMode::Exiting {
             terminal.draw(|frame| self.render_frame(frame))?;
             self.handle_events()?;
+            self.autosave();
+            self.check_disk();
 
             // command error timer update
             if let Some(frames) = self.frames_since_error {
@@ -134,12 +191,38 @@ impl App {
                 PopupStatus::Confirmed => {
                     self.save_window.close();
                     self.mode = RunningMode::Exiting;
-                    if self.save_window.decision() {
-                        self.command_field.set_text("t save".to_string());
-                        self.process_command();
+                    // stay open when saving failed so nothing is lost
+                    if self.save_window.decision()
+                        && let Err(e) = self.task_list_tab.save(&self.task_lists, &self.options)
+                    {
+                        self.mode = RunningMode::Running;
+                        self.report_save_error(e, true);
                     }
                 }
             }
+            match self.message_window.status {
+                PopupStatus::Closed | PopupStatus::InUse => {}
+                _ => self.message_window.close(),
+            }
+            match self.recovery_window.status {
+                PopupStatus::Closed | PopupStatus::InUse => {}
+                PopupStatus::Canceled => self.recovery_window.close(),
+                PopupStatus::Confirmed => {
+                    self.recovery_window.close();
+                    self.recover(self.recovery_window.choice());
+                }
+            }
+            match self.conflict_window.status {
+                PopupStatus::Closed | PopupStatus::InUse => {}
+                PopupStatus::Canceled => {
+                    self.conflict_window.close();
+                    self.conflict_pending = true;
+                }
+                PopupStatus::Confirmed => {
+                    self.conflict_window.close();
+                    self.resolve_conflict(self.conflict_window.choice());
+                }
+            }
         }
 
         // clean up
@@ -148,32 +231,243 @@ impl App {
         Ok(())
     }
 
+    fn load_on_start(&mut self) {
+        let data_file = self.task_list_tab.data_file.clone();
+        let result = self.task_list_tab.process_command(
+            "load".split(' '),
+            &mut self.task_lists,
+            &mut self.options,
+        );
+        self.task_list_tab.history.clear();
+        match result {
+            Ok(_) => {}
+            Err(
+                e @ (TaskCommandError::InvalidFileFormat { .. }
+                | TaskCommandError::UnsupportedVersion(_)),
+            ) => {
+                // the broken file is left alone until something changes and
+                // is then kept as a backup by the next save
+                self.task_lists = vec![TaskList::new("Tasks".to_string(), None)];
+                self.task_list_tab.mark_saved(&self.task_lists, None);
+                self.recovery_window.show(
+                    format!("{data_file} could not be loaded.\n{e}"),
+                    vec![
+                        "Restore last backup".to_string(),
+                        "Start empty".to_string(),
+                        "Quit".to_string(),
+                    ],
+                );
+            }
+            Err(e) => {
+                self.task_lists = vec![TaskList::new("Tasks".to_string(), None)];
+                self.task_list_tab.mark_saved(&self.task_lists, None);
+                self.post_error(e.to_string());
+            }
+        }
+    }
+
+    fn recover(&mut self, choice: usize) {
+        match choice {
+            0 => match self
+                .task_list_tab
+                .restore_latest_backup(&mut self.task_lists, &mut self.options)
+            {
+                Ok(name) => {
+                    self.task_list_tab.history.clear();
+                    self.post_info(format!("Restored {name}"));
+                }
+                Err(_) => {
+                    self.post_error("No usable backup, starting empty".to_string());
+                }
+            },
+            1 => self.post_info("Starting with an empty set of tasks".to_string()),
+            _ => self.force_quit(),
+        }
+    }
+
+    // a file changed on disk is left to the user, other errors are shown
+    fn report_save_error(&mut self, error: TaskCommandError, quit_after: bool) {
+        match error {
+            TaskCommandError::ModifiedOnDisk => self.show_conflict(quit_after),
+            e => self.post_error(e.to_string()),
+        }
+    }
+
+    fn show_conflict(&mut self, quit_after: bool) {
+        self.quit_after_conflict = quit_after;
+        self.conflict_window.show(
+            format!(
+                "{} was changed by another program since it was loaded.",
+                self.task_list_tab.data_file
+            ),
+            vec![
+                "Reload it, :undo brings your version back".to_string(),
+                "Overwrite it with your version".to_string(),
+                "Merge both".to_string(),
+                "Decide later".to_string(),
+            ],
+        );
+    }
+
+    /*
+        Reloads the data file after another program wrote it. Waits until no
+        popup or command is open so nothing being edited is swapped out, and
+        asks first when there is unsaved work.
+    */
+    fn check_disk(&mut self) {
+        if self.watcher.as_ref().is_some_and(|w| w.changed()) {
+            self.disk_changed = true;
+        }
+        if !self.disk_changed || self.mode != RunningMode::Running || self.popup_open() {
+            return;
+        }
+        self.disk_changed = false;
+        // our own saves are noticed by the watcher as well
+        if !storage::changed_on_disk(
+            &self.task_list_tab.data_file,
+            &self.task_list_tab.disk_stamp,
+        ) {
+            return;
+        }
+
+        if self.task_list_tab.is_unsaved() {
+            if !self.conflict_pending {
+                self.show_conflict(false);
+            }
+            return;
+        }
+        match self
+            .task_list_tab
+            .reload(&mut self.task_lists, &mut self.options)
+        {
+            Ok(()) => self.post_info(format!(
+                "Reloaded {}, it was changed on disk",
+                self.task_list_tab.data_file
+            )),
+            Err(e) => self.post_error(e.to_string()),
+        }
+    }
+
+    fn popup_open(&self) -> bool {
+        self.save_window.status == PopupStatus::InUse
+            || self.message_window.status == PopupStatus::InUse
+            || self.recovery_window.status == PopupStatus::InUse
+            || self.conflict_window.status == PopupStatus::InUse
+            || self.task_list_tab.popup_open()
+    }
+
+    fn resolve_conflict(&mut self, choice: usize) {
+        let result = match choice {
+            0 => self
+                .task_list_tab
+                .reload(&mut self.task_lists, &mut self.options)
+                .map(|_| "Reloaded the tasks from disk"),
+            1 => self
+                .task_list_tab
+                .overwrite(&self.task_lists, &self.options)
+                .map(|_| "Saved over the changes on disk"),
+            2 => self
+                .task_list_tab
+                .merge_from_disk(&mut self.task_lists, &self.options)
+                .map(|_| "Merged with the changes on disk"),
+            _ => {
+                self.conflict_pending = true;
+                return;
+            }
+        };
+        match result {
+            Ok(message) => {
+                self.conflict_pending = false;
+                self.post_info(message.to_string());
+                if self.quit_after_conflict {
+                    self.mode = RunningMode::Exiting;
+                }
+            }
+            Err(e) => self.post_error(e.to_string()),
+        }
+    }
+
     fn render_frame(&self, frame: &mut Frame) {
         frame.render_widget(self, frame.area());
     }
 
     fn handle_events(&mut self) -> io::Result<()> {
-        if event::poll(std::time::Duration::from_millis(16))? {
-            if let event::Event::Key(key) = event::read()? {
-                // key holds info about modifiers (shitf, ctrl, alt)
-                if key.kind == event::KeyEventKind::Press {
-                    if !self.dispatch_input(key.code) {
-                        match key.code {
-                            KeyCode::Char('q') => self.try_quit(),
-                            KeyCode::Tab => self.next_tab(),
-                            KeyCode::BackTab => self.previous_tab(),
-                            KeyCode::Char(':') => {
-                                self.mode = RunningMode::Command;
-                                self.frames_since_error = None;
-                                self.command_field.clear();
-                            }
-                            _ => {}
-                        }
-                    }
+        if !event::poll(std::time::Duration::from_millis(16))? {
+            return Ok(());
+        }
+        let event = event::read()?;
+        if event == event::Event::FocusLost && self.options.autosave != Autosave::Off {
+            self.save_if_unsaved();
+        }
+        if let event::Event::Key(key) = event
+            // key holds info about modifiers (shitf, ctrl, alt)
+            && key.kind == event::KeyEventKind::Press
+        {
+            self.last_input = Instant::now();
+            self.autosave_paused = false;
+            self.handle_key(key);
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: event::KeyEvent) {
+        if !self.dispatch_shortcut(key) && !self.dispatch_input(key.code) {
+            match key.code {
+                KeyCode::Char('q') => self.try_quit(),
+                KeyCode::Tab => self.next_tab(),
+                KeyCode::BackTab => self.previous_tab(),
+                KeyCode::Char(':') => {
+                    self.mode = RunningMode::Command;
+                    self.frames_since_error = None;
+                    self.command_field.clear();
                 }
+                _ => {}
             }
         }
-        Ok(())
+    }
+
+    // saves once the configured autosave condition is met
+    fn autosave(&mut self) {
+        let due = match self.options.autosave {
+            Autosave::Off => false,
+            Autosave::OnChange => true,
+            Autosave::AfterIdle(seconds) => self.last_input.elapsed().as_secs() >= seconds as u64,
+        };
+        if due
+            && !self.autosave_paused
+            && !self.conflict_pending
+            && self.conflict_window.status != PopupStatus::InUse
+            && self.task_list_tab.lock.is_some()
+            && self.mode == RunningMode::Running
+        {
+            self.save_if_unsaved();
+        }
+    }
+
+    fn save_if_unsaved(&mut self) {
+        if !self.task_list_tab.is_unsaved() {
+            return;
+        }
+        if let Err(e) = self.task_list_tab.save(&self.task_lists, &self.options) {
+            self.autosave_paused = true;
+            self.report_save_error(e, false);
+        }
+    }
+
+    // ctrl key combinations, which only apply while no text is being typed
+    fn dispatch_shortcut(&mut self, key: event::KeyEvent) -> bool {
+        if !key.modifiers.contains(KeyModifiers::CONTROL)
+            || self.mode != RunningMode::Running
+            || self.popup_open()
+        {
+            return false;
+        }
+
+        match key.code {
+            KeyCode::Char('r') => self.redo(),
+            _ => return false,
+        }
+        true
     }
 
     fn dispatch_input(&mut self, key: KeyCode) -> bool {
@@ -195,11 +489,23 @@ impl App {
                 _ => {}
             }
             true
+        } else if self.message_window.status == PopupStatus::InUse {
+            self.message_window.handle_input(key)
+        } else if self.recovery_window.status == PopupStatus::InUse {
+            self.recovery_window.handle_input(key)
+        } else if self.conflict_window.status == PopupStatus::InUse {
+            self.conflict_window.handle_input(key)
         } else if self.save_window.status == PopupStatus::InUse {
             self.save_window.handle_input(key)
         } else {
             match self.current_tab {
-                Tab::TaskList => self.task_list_tab.handle_input(&mut self.task_lists, key),
+                Tab::TaskList => {
+                    let captured = self.task_list_tab.handle_input(&mut self.task_lists, key);
+                    if let Some(e) = self.task_list_tab.error.take() {
+                        self.post_error(e.to_string());
+                    }
+                    captured
+                }
                 Tab::Calendar => self.calendar_tab.handle_input(key),
                 Tab::Options => self.options_tab.handle_input(key),
             }
@@ -210,39 +516,70 @@ impl App {
     fn process_command(&mut self) {
         let mut parsed_command = self.command_field.get_str().split(' ');
         match parsed_command.next().unwrap() {
-            "tasks" | "t" => match self
-                .task_list_tab
-                .process_command(parsed_command, &mut self.task_lists)
-            {
+            "tasks" | "t" => match self.task_list_tab.process_command(
+                parsed_command,
+                &mut self.task_lists,
+                &mut self.options,
+            ) {
                 Err(TaskCommandError::UnknownCommand) => self.post_error(format!(
                     "Unknown Command: \"{}\"",
                     self.command_field.get_str()
                 )),
-                Err(TaskCommandError::InvalidFilePath) => {
-                    self.post_error("Invalid File Path".to_string())
-                }
-                Err(TaskCommandError::InvalidFileFormat) => {
-                    self.post_error("Invalid File Format".to_string())
-                }
-                Err(TaskCommandError::MissingField) => self.post_error("Missing Field".to_string()),
-                Err(TaskCommandError::NotANumber) => self.post_error("Not A Number".to_string()),
-                Err(TaskCommandError::InvalidOption) => {
-                    self.post_error("Invalid Option".to_string())
-                }
+                Err(e) => self.report_save_error(e, false),
                 Ok(CommandRequest::None) => {}
                 Ok(CommandRequest::SetActive) => self.current_tab = Tab::TaskList,
             },
             "calendar" | "c" => self.current_tab = Tab::Calendar,
-            "options" | "o" => self.current_tab = Tab::Options,
+            "options" | "o" => match self.options_tab.process_command(
+                parsed_command,
+                &mut self.options,
+                &mut self.task_list_tab.history,
+            ) {
+                Err(TaskCommandError::UnknownCommand) => self.post_error(format!(
+                    "Unknown Command: \"{}\"",
+                    self.command_field.get_str()
+                )),
+                Err(e) => self.post_error(e.to_string()),
+                Ok(CommandRequest::None) => {}
+                Ok(CommandRequest::SetActive) => self.current_tab = Tab::Options,
+            },
+            "undo" => self.undo(),
+            "redo" => self.redo(),
             "quit" | "q" => self.try_quit(),
             "quit!" | "q!" => self.force_quit(),
             _ => self.post_error(format!("Unknown Command: {}", self.command_field.get_str())),
         }
     }
 
+    fn undo(&mut self) {
+        if let Err(e) = self.task_list_tab.undo(&mut self.task_lists) {
+            self.post_error(e.to_string());
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Err(e) = self.task_list_tab.redo(&mut self.task_lists) {
+            self.post_error(e.to_string());
+        }
+    }
+
     fn post_error(&mut self, err_str: String) {
         self.frames_since_error = Some(0);
-        self.error_str = err_str;
+        self.error_str = format!("Error: {err_str}");
+        self.error_style = THEME.command_error;
+
+        // the bottom bar cuts long messages off
+        let width = crossterm::terminal::size().map_or(80, |(w, _)| w as usize);
+        if self.error_str.chars().count() > width {
+            self.frames_since_error = None;
+            self.message_window.show(err_str, vec!["OK".to_string()]);
+        }
+    }
+
+    fn post_info(&mut self, info_str: String) {
+        self.frames_since_error = Some(0);
+        self.error_str = info_str;
+        self.error_style = THEME.command_info;
     }
 
     fn force_quit(&mut self) {
@@ -250,10 +587,7 @@ impl App {
     }
 
     fn try_quit(&mut self) {
-        let mut sorted = self.task_lists.clone();
-        sorted.sort();
-
-        if sorted == self.task_lists_backup {
+        if !self.task_list_tab.is_unsaved() {
             self.mode = RunningMode::Exiting;
         } else {
             self.save_window.show();
@@ -279,14 +613,22 @@ impl App {
     fn render_title_bar(&self, area: Rect, buf: &mut Buffer) {
         let horizontal = Layout::horizontal([
             Constraint::Min(0),
+            Constraint::Length(16),
             Constraint::Length(7),
             Constraint::Length(10),
             Constraint::Length(9),
         ]);
-        let [app_name, list_tab, calendar_tab, options_tab] = horizontal.areas(area);
+        let [app_name, save_status, list_tab, calendar_tab, options_tab] = horizontal.areas(area);
 
         Block::new().style(THEME.root).render(area, buf);
         Span::raw("FrogPad").render(app_name, buf);
+        match (self.task_list_tab.is_unsaved(), self.task_list_tab.saved_at) {
+            _ if self.task_list_tab.lock.is_none() => Span::styled("read-only", THEME.root_unsaved),
+            (true, _) => Span::styled("unsaved", THEME.root_unsaved),
+            (false, Some(time)) => Span::raw(format!("saved {}", time.format("%H:%M:%S"))),
+            (false, None) => Span::raw("saved"),
+        }
+        .render(save_status, buf);
         Span::raw(" Tasks ")
             .style(self.get_style(Tab::TaskList))
             .render(list_tab, buf);
@@ -332,21 +674,39 @@ impl App {
     }
 }
 
-fn main() -> io::Result<()> {
+fn main() -> io::Result<ExitCode> {
+    let args = match cli::Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => return Ok(cli::report(e)),
+    };
+    if let Some(command) = args.command {
+        return Ok(cli::run(command, &args.data_file));
+    }
+
+    let options = Options::default();
+    // without a lock the file is only read, whatever kept it from being taken
+    let (lock, lock_error) = match storage::lock_data(&args.data_file) {
+        Ok(lock) => (lock, None),
+        Err(e) => (None, Some(e)),
+    };
+    let watcher = watcher::FileWatcher::new(&args.data_file);
+
     let mut terminal = tui::init()?;
     let mut app = App {
         mode: RunningMode::Running,
         current_tab: Tab::TaskList,
         command_field: TextEntry::default(),
         error_str: String::new(),
+        error_style: THEME.command_error,
         frames_since_error: None,
         task_lists: Vec::new(),
-        task_lists_backup: Vec::new(),
-        options: Options {
-            delete_on_completion: false,
-            error_display_time: 2,
-            refresh_rate: 60,
-        },
+        last_input: Instant::now(),
+        autosave_paused: false,
+        conflict_pending: false,
+        quit_after_conflict: false,
+        watcher,
+        disk_changed: false,
+        lock_error,
         task_list_tab: TaskListTab {
             controls: [
                 ("J", "Down"),
@@ -362,15 +722,32 @@ fn main() -> io::Result<()> {
                 "Are you sure you want to delete?".to_string(),
             ),
             new_tasklist_window: TextEntryPopup::new("Enter TaskList Name".to_string(), 3),
-            selected_sub_task: 0,
+            changes_window: ChoicePopup::new("Changes".to_string()),
+            import_window: ChoicePopup::new("Import".to_string()),
+            journal: journal::Journal::new(&args.data_file),
+            data_file: args.data_file,
+            data_dir: args.data_dir,
+            saved_lists: Vec::new(),
+            saved_revision: 0,
+            saved_at: None,
+            lock,
+            disk_stamp: None,
+            filter: None,
+            error: None,
+            history: History::new(options.history_depth),
         },
+        options,
         calendar_tab: CalendarTab::default(),
         options_tab: OptionsTab {},
         save_window: ConfirmationPopup::new(
             "Confirm Save".to_string(),
             "There is unsaved work. Save and Quit?".to_string(),
         ),
+        message_window: ChoicePopup::new("Error".to_string()),
+        recovery_window: ChoicePopup::new("Could Not Load Tasks".to_string()),
+        conflict_window: ChoicePopup::new("Tasks Changed On Disk".to_string()),
     };
     app.run(&mut terminal)?;
-    tui::restore()
+    tui::restore()?;
+    Ok(ExitCode::SUCCESS)
 }
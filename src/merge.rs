@@ -0,0 +1,229 @@
+use crate::tabs::{
+    Task, TaskList, assign_task_ids, find_task, next_task_id, remove_task, task_by_id_mut,
+};
+use std::collections::{HashMap, HashSet};
+
+/*
+    Three way merge of task lists, used when the data file was changed by
+    another program while there was unsaved work. Tasks are matched by id
+    and the changes made here since base are replayed on top of the file:
+    edits to the same task on both sides keep the local version, and a task
+    deleted on either side stays deleted.
+*/
+
+// where a task sits and what it holds, without its sub-tasks
+struct Entry {
+    list: String,
+    parent: Option<u64>,
+    index: usize,
+    task: Task,
+}
+
+// every task in pre-order, so parents come before their children
+fn flatten(task_lists: &[TaskList]) -> Vec<(u64, Entry)> {
+    fn walk(list: &str, parent: Option<u64>, tasks: &[Task], entries: &mut Vec<(u64, Entry)>) {
+        for (index, task) in tasks.iter().enumerate() {
+            let entry = Entry {
+                list: list.to_string(),
+                parent,
+                index,
+                task: Task {
+                    sub_tasks: Vec::new(),
+                    ..task.clone()
+                },
+            };
+            entries.push((task.id, entry));
+            walk(list, Some(task.id), &task.sub_tasks, entries);
+        }
+    }
+
+    let mut entries = Vec::new();
+    for list in task_lists {
+        walk(&list.name, None, &list.tasks, &mut entries);
+    }
+    entries
+}
+
+// places a task below parent, or at the top of list when parent is gone
+fn insert(
+    task_lists: &mut Vec<TaskList>,
+    list: &str,
+    parent: Option<u64>,
+    index: usize,
+    task: Task,
+) {
+    if let Some(parent) = parent
+        && let Some(parent) = task_by_id_mut(task_lists, parent)
+    {
+        let index = index.min(parent.sub_tasks.len());
+        parent.sub_tasks.insert(index, task);
+        return;
+    }
+
+    let position = match task_lists.iter().position(|l| l.name == list) {
+        Some(position) => position,
+        None => {
+            task_lists.push(TaskList::new(list.to_string(), None));
+            task_lists.len() - 1
+        }
+    };
+    let tasks = &mut task_lists[position].tasks;
+    tasks.insert(index.min(tasks.len()), task);
+}
+
+pub fn merge(base: &[TaskList], mine: &[TaskList], theirs: Vec<TaskList>) -> Vec<TaskList> {
+    let mut merged = theirs;
+    assign_task_ids(&mut merged);
+
+    // lists removed or added here
+    for list in base {
+        if !mine.iter().any(|l| l.name == list.name) {
+            merged.retain(|l| l.name != list.name);
+        }
+    }
+    for list in mine {
+        if !base.iter().any(|l| l.name == list.name) && !merged.iter().any(|l| l.name == list.name)
+        {
+            merged.push(TaskList::new(list.name.clone(), None));
+        }
+    }
+
+    let base_entries: HashMap<u64, Entry> = flatten(base).into_iter().collect();
+    let mine_entries = flatten(mine);
+    let mine_ids: HashSet<u64> = mine_entries.iter().map(|(id, _)| *id).collect();
+
+    for id in base_entries.keys() {
+        if !mine_ids.contains(id) {
+            remove_task(&mut merged, *id);
+        }
+    }
+
+    // tasks created here whose id was taken on disk in the meantime
+    let mut renamed: HashMap<u64, u64> = HashMap::new();
+    let mut touched = Vec::new();
+    for (id, entry) in mine_entries {
+        let parent = entry.parent.map(|p| *renamed.get(&p).unwrap_or(&p));
+        match base_entries.get(&id) {
+            None => {
+                let mut task = entry.task;
+                if find_task(&merged, id).is_some() {
+                    task.id = next_task_id();
+                    renamed.insert(id, task.id);
+                }
+                touched.push(task.id);
+                insert(&mut merged, &entry.list, parent, entry.index, task);
+            }
+            Some(old) => {
+                if old.task != entry.task
+                    && let Some(task) = task_by_id_mut(&mut merged, id)
+                {
+                    let sub_tasks = std::mem::take(&mut task.sub_tasks);
+                    *task = Task {
+                        sub_tasks,
+                        ..entry.task
+                    };
+                    touched.push(id);
+                }
+                if (old.list != entry.list || old.parent != entry.parent)
+                    && let Some(task) = remove_task(&mut merged, id)
+                {
+                    insert(&mut merged, &entry.list, parent, entry.index, task);
+                }
+            }
+        }
+    }
+
+    // blockers may point at tasks that were given a new id above
+    for id in touched {
+        if let Some(task) = task_by_id_mut(&mut merged, id) {
+            for blocker in task.blocked_by.iter_mut() {
+                *blocker = *renamed.get(blocker).unwrap_or(blocker);
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: u64, title: &str) -> Task {
+        Task {
+            id,
+            title: title.to_string(),
+            ..Task::default()
+        }
+    }
+
+    fn lists(tasks: Vec<Task>) -> Vec<TaskList> {
+        let mut lists = vec![TaskList::new("Tasks".to_string(), Some(tasks))];
+        // ids handed out from here on do not collide with these
+        assign_task_ids(&mut lists);
+        lists
+    }
+
+    fn titles(tasks: &[Task]) -> Vec<&str> {
+        tasks.iter().map(|t| t.title.as_str()).collect()
+    }
+
+    #[test]
+    fn both_sides_edit_the_same_task() {
+        let base = lists(vec![task(1, "a"), task(2, "b")]);
+        let mine = lists(vec![task(1, "a mine"), task(2, "b")]);
+        let theirs = lists(vec![task(1, "a theirs"), task(2, "b theirs")]);
+
+        let merged = merge(&base, &mine, theirs);
+        assert_eq!(titles(&merged[0].tasks), ["a mine", "b theirs"]);
+    }
+
+    #[test]
+    fn deletes_win_over_edits() {
+        let base = lists(vec![task(1, "a"), task(2, "b")]);
+        // a is edited here and deleted on disk, b the other way round
+        let mine = lists(vec![task(1, "a mine")]);
+        let theirs = lists(vec![task(2, "b theirs")]);
+
+        let merged = merge(&base, &mine, theirs);
+        assert!(merged[0].tasks.is_empty());
+    }
+
+    #[test]
+    fn new_ids_taken_on_disk_are_renamed() {
+        let base = lists(vec![task(1, "a")]);
+        let mine = lists(vec![
+            task(1, "a"),
+            task(5, "new here"),
+            Task {
+                blocked_by: vec![5],
+                ..task(6, "blocked here")
+            },
+        ]);
+        let theirs = lists(vec![task(1, "a"), task(5, "new on disk")]);
+
+        let merged = merge(&base, &mine, theirs);
+        assert_eq!(
+            titles(&merged[0].tasks),
+            ["a", "new here", "blocked here", "new on disk"]
+        );
+        let new_here = merged[0].tasks[1].id;
+        assert_ne!(new_here, 5);
+        let (list, path) = find_task(&merged, 5).unwrap();
+        assert_eq!(merged[list].get_task(&path).unwrap().title, "new on disk");
+        assert_eq!(merged[0].tasks[2].blocked_by, [new_here]);
+    }
+
+    #[test]
+    fn moves_keep_edits_from_disk() {
+        let base = lists(vec![task(1, "parent"), task(2, "child")]);
+        let mine = lists(vec![Task {
+            sub_tasks: vec![task(2, "child")],
+            ..task(1, "parent")
+        }]);
+        let theirs = lists(vec![task(1, "parent"), task(2, "child theirs")]);
+
+        let merged = merge(&base, &mine, theirs);
+        assert_eq!(titles(&merged[0].tasks), ["parent"]);
+        assert_eq!(titles(&merged[0].tasks[0].sub_tasks), ["child theirs"]);
+    }
+}
@@ -1,9 +1,10 @@
 use crate::{
-    tabs::{Task, TaskStatus, disp_md},
+    recurrence::Recurrence,
+    tabs::{Priority, Task, TaskCommandError, TaskStatus, disp_md, extract_tags, parse_date},
     theme::THEME,
-    widgets::TextEntry,
+    widgets::{TextArea, TextEntry},
 };
-use chrono::{Datelike, Days, NaiveDate};
+use chrono::NaiveDate;
 use crossterm::event::KeyCode;
 use ratatui::{
     layout::Flex,
@@ -23,10 +24,14 @@ pub enum PopupStatus {
 #[derive(Default, PartialEq, Clone, Copy)]
 enum TaskEditorField {
     #[default]
-    Description,
+    Title,
+    Tags,
+    Repeat,
     Status,
+    Priority,
     Date,
     Duration,
+    Notes,
     //Cancel,
     //Confirm,
 }
@@ -34,10 +39,14 @@ enum TaskEditorField {
 impl TaskEditorField {
     fn next(&mut self) {
         *self = match self {
-            TaskEditorField::Description => TaskEditorField::Status,
-            TaskEditorField::Status => TaskEditorField::Date,
+            TaskEditorField::Title => TaskEditorField::Tags,
+            TaskEditorField::Tags => TaskEditorField::Repeat,
+            TaskEditorField::Repeat => TaskEditorField::Status,
+            TaskEditorField::Status => TaskEditorField::Priority,
+            TaskEditorField::Priority => TaskEditorField::Date,
             TaskEditorField::Date => TaskEditorField::Duration,
-            TaskEditorField::Duration => TaskEditorField::Description,
+            TaskEditorField::Duration => TaskEditorField::Notes,
+            TaskEditorField::Notes => TaskEditorField::Title,
             //TaskEditorField::Cancel => TaskEditorField::Confirm,
             //TaskEditorField::Confirm => TaskEditorField::Description,
         }
@@ -45,10 +54,14 @@ impl TaskEditorField {
 
     fn previous(&mut self) {
         *self = match self {
-            TaskEditorField::Description => TaskEditorField::Duration,
-            TaskEditorField::Status => TaskEditorField::Description,
-            TaskEditorField::Date => TaskEditorField::Status,
+            TaskEditorField::Title => TaskEditorField::Notes,
+            TaskEditorField::Tags => TaskEditorField::Title,
+            TaskEditorField::Repeat => TaskEditorField::Tags,
+            TaskEditorField::Status => TaskEditorField::Repeat,
+            TaskEditorField::Priority => TaskEditorField::Status,
+            TaskEditorField::Date => TaskEditorField::Priority,
             TaskEditorField::Duration => TaskEditorField::Date,
+            TaskEditorField::Notes => TaskEditorField::Duration,
             //TaskEditorField::Cancel => TaskEditorField::Duration,
             //TaskEditorField::Confirm => TaskEditorField::Cancel,
         }
@@ -59,6 +72,8 @@ impl TaskEditorField {
 pub enum TaskSource {
     #[default]
     New,
+    // added under the selected task
+    Child,
     Existing,
 }
 
@@ -72,20 +87,52 @@ pub struct TaskEditorPopup {
     task: Task,
     selected_field: TaskEditorField,
 
-    desc_field: TextEntry,
+    title_field: TextEntry,
+    tags_field: TextEntry,
+    repeat_field: TextEntry,
+    notes_field: TextArea,
 
     editing_date: bool,
     date_field: TextEntry,
+
+    // a field that could not be read, shown by the task list
+    pub error: Option<TaskCommandError>,
 }
 
 impl TaskEditorPopup {
     pub fn handle_input(&mut self, key: KeyCode) -> bool {
         match self.selected_field {
-            TaskEditorField::Description => match key {
-                KeyCode::Char(c) => self.desc_field.insert(c),
-                KeyCode::Backspace => self.desc_field.remove(),
-                KeyCode::Left => self.desc_field.move_cursor_left(),
-                KeyCode::Right => self.desc_field.move_cursor_right(),
+            TaskEditorField::Title => match key {
+                KeyCode::Char(c) => self.title_field.insert(c),
+                KeyCode::Backspace => self.title_field.remove(),
+                KeyCode::Left => self.title_field.move_cursor_left(),
+                KeyCode::Right => self.title_field.move_cursor_right(),
+                _ => {}
+            },
+            TaskEditorField::Tags => match key {
+                KeyCode::Char(c) => self.tags_field.insert(c),
+                KeyCode::Backspace => self.tags_field.remove(),
+                KeyCode::Left => self.tags_field.move_cursor_left(),
+                KeyCode::Right => self.tags_field.move_cursor_right(),
+                _ => {}
+            },
+            TaskEditorField::Repeat => match key {
+                KeyCode::Char(c) => self.repeat_field.insert(c),
+                KeyCode::Backspace => self.repeat_field.remove(),
+                KeyCode::Left => self.repeat_field.move_cursor_left(),
+                KeyCode::Right => self.repeat_field.move_cursor_right(),
+                _ => {}
+            },
+            TaskEditorField::Notes => match key {
+                KeyCode::Char(c) => self.notes_field.insert(c),
+                KeyCode::Enter => self.notes_field.newline(),
+                KeyCode::Backspace => self.notes_field.remove(),
+                KeyCode::Left => self.notes_field.move_cursor_left(),
+                KeyCode::Right => self.notes_field.move_cursor_right(),
+                KeyCode::Up => self.notes_field.move_cursor_up(),
+                KeyCode::Down => self.notes_field.move_cursor_down(),
+                KeyCode::Home => self.notes_field.move_cursor_home(),
+                KeyCode::End => self.notes_field.move_cursor_end(),
                 _ => {}
             },
             /*
@@ -105,35 +152,43 @@ impl TaskEditorPopup {
                 KeyCode::Char('j') => self.task.status.cycle_next(),
                 _ => {}
             },
+            TaskEditorField::Priority => match key {
+                KeyCode::Char(c @ '0'..='4') => {
+                    self.task.priority = Priority::from_name(&c.to_string()).unwrap_or_default()
+                }
+                KeyCode::Char('k') | KeyCode::Char('+') => self.task.priority.raise(),
+                KeyCode::Char('j') | KeyCode::Char('-') => self.task.priority.lower(),
+                _ => {}
+            },
             TaskEditorField::Date => match key {
                 KeyCode::Char('j') => self.task.date = self.task.date.succ_opt().unwrap(),
                 KeyCode::Char('k') => self.task.date = self.task.date.pred_opt().unwrap(),
                 KeyCode::Tab | KeyCode::Char('/') if self.editing_date => {
                     self.submit_date();
                 }
-                KeyCode::Backspace => {
-                    if self.editing_date {
-                        self.date_field.remove();
-                    }
-                }
-                KeyCode::Char(c) if c >= '0' && c <= '9' || c == '+' || c == '-' => {
+                KeyCode::Backspace if self.editing_date => self.date_field.remove(),
+                KeyCode::Char(c) if c.is_ascii_digit() || c == '+' || c == '-' => {
                     self.editing_date = true;
                     self.date_field.insert(c);
                 }
                 _ => {}
             },
-            TaskEditorField::Duration => match key {
-                _ => {}
-            },
+            TaskEditorField::Duration => {}
         }
 
         match key {
+            // enter starts a new line while writing notes
+            KeyCode::Enter if self.selected_field == TaskEditorField::Notes => {}
             KeyCode::Enter => {
                 if self.editing_date {
                     self.submit_date();
+                } else if let Err(e) = self.submit_repeat() {
+                    self.selected_field = TaskEditorField::Repeat;
+                    self.error = Some(e);
                 } else {
                     self.status = PopupStatus::Confirmed;
-                    self.task.name = self.desc_field.take();
+                    self.submit_tags();
+                    self.task.notes = self.notes_field.take();
                 }
             }
             KeyCode::Esc => self.status = PopupStatus::Closed,
@@ -152,19 +207,44 @@ impl TaskEditorPopup {
 
     pub fn edit_task(&mut self, task: Task) {
         self.task = task;
-        self.desc_field.set_text(self.task.name.clone());
-        self.desc_field.move_cursor_end();
+        self.title_field.set_text(self.task.title.clone());
+        self.title_field.move_cursor_end();
+        self.tags_field.set_text(self.task.tags.join(" "));
+        self.repeat_field.set_text(
+            self.task
+                .recurrence
+                .as_ref()
+                .map_or(String::new(), |r| r.to_string()),
+        );
+        self.notes_field.set_text(&self.task.notes);
         self.status = PopupStatus::InUse;
+        self.selected_field = TaskEditorField::Title;
         self.task_source = TaskSource::Existing;
+        self.editing_date = false;
+        self.date_field.clear();
     }
 
     pub fn new_task(&mut self) {
         self.status = PopupStatus::InUse;
-        self.selected_field = TaskEditorField::Description;
+        self.selected_field = TaskEditorField::Title;
         self.task_source = TaskSource::New;
 
-        self.task.date = chrono::offset::Local::now().date_naive();
-        self.desc_field.move_cursor_end();
+        // nothing of a task edited and then dropped with esc carries over
+        self.task = Task {
+            date: chrono::offset::Local::now().date_naive(),
+            ..Task::default()
+        };
+        self.title_field.clear();
+        self.tags_field.clear();
+        self.repeat_field.clear();
+        self.notes_field.clear();
+        self.editing_date = false;
+        self.date_field.clear();
+    }
+
+    pub fn new_child_task(&mut self) {
+        self.new_task();
+        self.task_source = TaskSource::Child;
     }
 
     fn get_style(&self, field: TaskEditorField) -> Style {
@@ -176,10 +256,40 @@ impl TaskEditorPopup {
         }
     }
 
+    // tags come from the tags field plus any #tag written in the title
+    fn submit_tags(&mut self) {
+        let (title, title_tags) = extract_tags(&self.title_field.take());
+        self.task.title = title;
+        self.task.tags.clear();
+        for tag in self
+            .tags_field
+            .take()
+            .split_whitespace()
+            .chain(title_tags.iter().map(String::as_str))
+        {
+            self.task.add_tag(tag);
+        }
+    }
+
+    // an empty rule stops the task repeating, an invalid one is left in the
+    // field to be corrected
+    fn submit_repeat(&mut self) -> Result<(), TaskCommandError> {
+        let rule = self.repeat_field.get_str();
+        if rule.trim().is_empty() {
+            self.task.recurrence = None;
+        } else {
+            let mut recurrence = rule.parse::<Recurrence>()?;
+            recurrence.keep_anchor(self.task.recurrence.as_ref());
+            self.task.recurrence = Some(recurrence);
+        }
+        self.repeat_field.clear();
+        Ok(())
+    }
+
     // consumes date entry string and updates date if pattern is valid
     fn submit_date(&mut self) {
         match self.parse_date() {
-            None => {}
+            None => self.error = Some(TaskCommandError::InvalidOption),
             Some(d) => self.task.date = d,
         }
         self.editing_date = false;
@@ -187,41 +297,13 @@ impl TaskEditorPopup {
     }
 
     fn parse_date(&mut self) -> Option<NaiveDate> {
-        let today = chrono::offset::Local::now().date_naive();
-
-        // +d adds d days to todays date
-        if self.date_field.get_str().chars().nth(0)? == '+' {
-            today.checked_add_days(Days::new(
-                self.date_field.get_str().get(1..)?.parse::<u64>().ok()?,
-            ))
-        // -d adds d days to todays date
-        } else if self.date_field.get_str().chars().nth(0)? == '-' {
-            today.checked_sub_days(Days::new(
-                self.date_field.get_str().get(1..)?.parse::<u64>().ok()?,
-            ))
-        // mmdd sets date to (current_year, mm, dd)
-        } else if self.date_field.get_str().len() == 4 {
-            NaiveDate::from_ymd_opt(
-                today.year(),
-                self.date_field.get_str().get(0..2)?.parse::<u32>().ok()?,
-                self.date_field.get_str().get(2..4)?.parse::<u32>().ok()?,
-            )
-        // yyyymmdd sets date to (yyyy, mm, dd)
-        } else if self.date_field.get_str().len() == 8 {
-            NaiveDate::from_ymd_opt(
-                self.date_field.get_str().get(0..4)?.parse::<i32>().ok()?,
-                self.date_field.get_str().get(4..6)?.parse::<u32>().ok()?,
-                self.date_field.get_str().get(6..8)?.parse::<u32>().ok()?,
-            )
-        } else {
-            None
-        }
+        parse_date(self.date_field.get_str())
     }
 }
 
 impl Widget for &TaskEditorPopup {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let vertical = Layout::vertical([5]).flex(Flex::Center);
+        let vertical = Layout::vertical([13]).flex(Flex::Center);
         let horizontal = Layout::horizontal([60]).flex(Flex::Center);
         let [area] = vertical.areas(area);
         let [area] = horizontal.areas(area);
@@ -230,33 +312,58 @@ impl Widget for &TaskEditorPopup {
             .style(THEME.popup)
             .border_style(THEME.popup)
             .border_type(BorderType::Rounded)
-            .title(Line::from(if let TaskSource::New = self.task_source {
-                "New Task"
-            } else {
-                "Edit Task"
+            .title(Line::from(match self.task_source {
+                TaskSource::New => "New Task",
+                TaskSource::Child => "New Sub-task",
+                TaskSource::Existing => "Edit Task",
             }))
             .title_bottom(
-                Line::raw(format!(" [Esc] to Cancel [Enter] to Confirm ")).right_aligned(),
+                Line::raw(if self.selected_field == TaskEditorField::Notes {
+                    " [Esc] to Cancel [Tab] to Leave Notes "
+                } else {
+                    " [Esc] to Cancel [Enter] to Confirm "
+                })
+                .right_aligned(),
             );
 
         let win_area = window.inner(area);
         Clear.render(win_area, buf);
         window.render(area, buf);
 
-        let vert = Layout::vertical([1, 1, 1]);
-        let [top_area, mid_area, _bot_area] = vert.areas(win_area);
+        let vert = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ]);
+        let [
+            top_area,
+            tags_area,
+            repeat_area,
+            mid_area,
+            notes_label_area,
+            notes_area,
+        ] = vert.areas(win_area);
 
         let mid_horiz = Layout::horizontal([
             Constraint::Length(10),
             Constraint::Length(15),
+            Constraint::Length(15),
             Constraint::Length(14),
         ]);
-        let [status_area, date_area, duration_area] = mid_horiz.areas(mid_area);
+        let [status_area, priority_area, date_area, duration_area] = mid_horiz.areas(mid_area);
         Span::styled(
             format!("Status: {}", self.task.status.get_symbol()),
             self.get_style(TaskEditorField::Status),
         )
         .render(status_area, buf);
+        Span::styled(
+            format!("Pri: {}", self.task.priority.get_name()),
+            self.get_style(TaskEditorField::Priority),
+        )
+        .render(priority_area, buf);
         Span::styled(
             format!(
                 "Date: {}",
@@ -287,19 +394,58 @@ impl Widget for &TaskEditorPopup {
         let top_horiz = Layout::horizontal([Constraint::Min(0)]);
         let [text_area] = top_horiz.areas(top_area);
         Line::from(vec![
-            Span::from("Desc: "),
-            Span::from(self.desc_field.get_str()),
+            Span::from("Title: "),
+            Span::from(self.title_field.get_str()),
         ])
-        .style(self.get_style(TaskEditorField::Description))
+        .style(self.get_style(TaskEditorField::Title))
         .render(text_area, buf);
 
-        if self.selected_field == TaskEditorField::Description {
+        if self.selected_field == TaskEditorField::Title {
             buf[(
-                text_area.x + 6 + self.desc_field.get_cursor_pos() as u16,
+                text_area.x + 7 + self.title_field.get_cursor_pos() as u16,
                 text_area.y,
             )]
                 .set_style(THEME.popup_cursor);
         }
+
+        Line::from(vec![
+            Span::from("Tags: "),
+            Span::from(self.tags_field.get_str()),
+        ])
+        .style(self.get_style(TaskEditorField::Tags))
+        .render(tags_area, buf);
+
+        if self.selected_field == TaskEditorField::Tags {
+            buf[(
+                tags_area.x + 6 + self.tags_field.get_cursor_pos() as u16,
+                tags_area.y,
+            )]
+                .set_style(THEME.popup_cursor);
+        }
+
+        Line::from(vec![
+            Span::from("Repeat: "),
+            Span::from(self.repeat_field.get_str()),
+        ])
+        .style(self.get_style(TaskEditorField::Repeat))
+        .render(repeat_area, buf);
+
+        if self.selected_field == TaskEditorField::Repeat {
+            buf[(
+                repeat_area.x + 8 + self.repeat_field.get_cursor_pos() as u16,
+                repeat_area.y,
+            )]
+                .set_style(THEME.popup_cursor);
+        }
+
+        Span::styled("Notes:", self.get_style(TaskEditorField::Notes))
+            .render(notes_label_area, buf);
+        self.notes_field.render(
+            notes_area,
+            buf,
+            THEME.popup,
+            (self.selected_field == TaskEditorField::Notes).then_some(THEME.popup_cursor),
+        );
     }
 }
 
@@ -523,3 +669,127 @@ impl Widget for &ConfirmationPopup {
         .render(button_area, buf);
     }
 }
+
+/*
+    A message followed by a list of actions to pick from, for when a plain
+    yes or no does not cover the ways forward. A single choice makes it a
+    message box.
+*/
+pub struct ChoicePopup {
+    pub title: String,
+    pub body: String,
+    pub choices: Vec<String>,
+    pub status: PopupStatus,
+
+    selected: usize,
+}
+
+impl ChoicePopup {
+    pub fn new(new_title: String) -> ChoicePopup {
+        ChoicePopup {
+            title: new_title,
+            body: String::new(),
+            choices: Vec::new(),
+            status: PopupStatus::Closed,
+            selected: 0,
+        }
+    }
+
+    pub fn handle_input(&mut self, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Tab | KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1) % self.choices.len().max(1);
+            }
+            KeyCode::BackTab | KeyCode::Up | KeyCode::Char('k') => {
+                let len = self.choices.len().max(1);
+                self.selected = (self.selected + len - 1) % len;
+            }
+            // choices are numbered from 1 on screen
+            KeyCode::Char(c) if c.is_ascii_digit() => {
+                let index = (c as usize).wrapping_sub('1' as usize);
+                if index < self.choices.len() {
+                    self.selected = index;
+                    self.status = PopupStatus::Confirmed;
+                }
+            }
+            KeyCode::Esc => self.status = PopupStatus::Canceled,
+            KeyCode::Enter => self.status = PopupStatus::Confirmed,
+            _ => {}
+        }
+
+        true
+    }
+
+    pub fn show(&mut self, body: String, choices: Vec<String>) {
+        self.body = body;
+        self.choices = choices;
+        self.selected = 0;
+        self.status = PopupStatus::InUse;
+    }
+
+    pub fn close(&mut self) {
+        self.status = PopupStatus::Closed;
+    }
+
+    pub fn choice(&self) -> usize {
+        self.selected
+    }
+}
+
+impl Widget for &ChoicePopup {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let width = area.width.min(64);
+        let body_width = width.saturating_sub(2).max(1) as usize;
+        let body_height: usize = self
+            .body
+            .lines()
+            .map(|line| line.chars().count() / body_width + 1)
+            .sum();
+        let height = (body_height + self.choices.len() + 3) as u16;
+
+        let vertical = Layout::vertical([height]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([width]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+
+        let window = Block::bordered()
+            .style(THEME.popup)
+            .border_style(THEME.popup)
+            .border_type(BorderType::Rounded)
+            .title(Span::from(&self.title))
+            .title_bottom(Line::raw(" [Esc] to Cancel [Enter] to Confirm ").right_aligned());
+
+        let win_area = window.inner(area);
+        Clear.render(win_area, buf);
+        window.render(area, buf);
+
+        let vertical = Layout::vertical([
+            Constraint::Length(body_height as u16),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ]);
+        let [body_area, _gap, choices_area] = vertical.areas(win_area);
+
+        Paragraph::new(self.body.as_str())
+            .style(THEME.popup)
+            .wrap(Wrap { trim: false })
+            .render(body_area, buf);
+
+        let lines: Vec<Line> = self
+            .choices
+            .iter()
+            .enumerate()
+            .map(|(index, choice)| {
+                Line::styled(
+                    format!(" {}. {choice} ", index + 1),
+                    if index == self.selected {
+                        THEME.popup_selected
+                    } else {
+                        THEME.popup
+                    },
+                )
+            })
+            .collect();
+        Text::from(lines).render(choices_area, buf);
+    }
+}
@@ -0,0 +1,436 @@
+use crate::tabs::{TaskCommandError, parse_date};
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, str::FromStr};
+
+/*
+    Rules for repeating tasks. A rule is written as
+        <frequency>[/<interval>] [<on>] [until <date>]
+    e.g. "daily", "weekly/2 mon,thu", "monthly 1", "yearly until 20301231".
+*/
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Frequency {
+    Daily,
+    // no weekdays repeats on the weekday of the task date
+    Weekly(Vec<Weekday>),
+    // no day repeats on the day of month the series started on
+    Monthly(Option<u32>),
+    Yearly,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub until: Option<NaiveDate>,
+    // date the series started on. Monthly rules without a day and yearly
+    // rules keep to its day, so a short month does not move every later date
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<NaiveDate>,
+}
+
+// weekdays have no natural order so rules are ordered by how they are written
+impl PartialOrd for Recurrence {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Recurrence {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.to_string().cmp(&other.to_string())
+    }
+}
+
+fn weekday_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "mon",
+        Weekday::Tue => "tue",
+        Weekday::Wed => "wed",
+        Weekday::Thu => "thu",
+        Weekday::Fri => "fri",
+        Weekday::Sat => "sat",
+        Weekday::Sun => "sun",
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let next = first.checked_add_months(Months::new(1)).unwrap();
+    next.signed_duration_since(first).num_days() as u32
+}
+
+// the given day of the month, moved back to the last day for short months
+fn clamped_day(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, day.min(days_in_month(year, month)))
+}
+
+impl Recurrence {
+    // the first occurrence strictly after from, None once the rule has ended.
+    // Without an anchor the series is taken to start at from
+    pub fn next_after(&self, from: NaiveDate) -> Option<NaiveDate> {
+        let anchor = self.anchor.unwrap_or(from);
+        let interval = self.interval.max(1);
+        let next = match &self.frequency {
+            Frequency::Daily => from.checked_add_days(Days::new(interval as u64))?,
+            Frequency::Weekly(days) if days.is_empty() => {
+                from.checked_add_days(Days::new(7 * interval as u64))?
+            }
+            Frequency::Weekly(days) => {
+                let week_start =
+                    from.checked_sub_days(Days::new(from.weekday().num_days_from_monday() as u64))?;
+                // remaining days of this week, then the matching week after the interval
+                let rest_of_week = (from.weekday().num_days_from_monday() + 1..7)
+                    .map(|offset| week_start.checked_add_days(Days::new(offset as u64)));
+                let next_week_start =
+                    week_start.checked_add_days(Days::new(7 * interval as u64))?;
+                let next_week =
+                    (0..7).map(|offset| next_week_start.checked_add_days(Days::new(offset)));
+                rest_of_week
+                    .chain(next_week)
+                    .flatten()
+                    .find(|day| days.contains(&day.weekday()))?
+            }
+            Frequency::Monthly(day) => {
+                let day = day.unwrap_or(anchor.day());
+                match clamped_day(from.year(), from.month(), day) {
+                    Some(date) if date > from => date,
+                    _ => {
+                        let month = from
+                            .with_day(1)?
+                            .checked_add_months(Months::new(interval))?;
+                        clamped_day(month.year(), month.month(), day)?
+                    }
+                }
+            }
+            Frequency::Yearly => match clamped_day(from.year(), anchor.month(), anchor.day()) {
+                Some(date) if date > from => date,
+                _ => clamped_day(
+                    from.year().checked_add(interval as i32)?,
+                    anchor.month(),
+                    anchor.day(),
+                )?,
+            },
+        };
+
+        match self.until {
+            Some(until) if next > until => None,
+            _ => Some(next),
+        }
+    }
+
+    // the same rule set again on a task continues the series it replaces
+    pub fn keep_anchor(&mut self, old: Option<&Recurrence>) {
+        if let Some(old) = old
+            && old.to_string() == self.to_string()
+        {
+            self.anchor = old.anchor;
+        }
+    }
+
+    /*
+        The last occurrence of a series starting at start in the period
+        (day, week, month or year of the rule) before from, or the first
+        one in the period of from. Walking on from there reaches every
+        occurrence on or after from without going through the older ones.
+        None once the rule has ended.
+    */
+    fn resume_at(&self, start: NaiveDate, from: NaiveDate) -> Option<NaiveDate> {
+        let interval = self.interval.max(1);
+        let monday = |date: NaiveDate| {
+            date.checked_sub_days(Days::new(date.weekday().num_days_from_monday() as u64))
+        };
+        let resumed = match &self.frequency {
+            Frequency::Daily => {
+                let days = (from - start).num_days() as u64 / interval as u64 * interval as u64;
+                start.checked_add_days(Days::new(days))?
+            }
+            Frequency::Weekly(days) if days.is_empty() => {
+                let step = 7 * interval as u64;
+                let days = (from - start).num_days() as u64 / step * step;
+                start.checked_add_days(Days::new(days))?
+            }
+            Frequency::Weekly(days) => {
+                let weeks = (monday(from)? - monday(start)?).num_weeks() as u64;
+                let weeks = weeks / interval as u64 * interval as u64;
+                if weeks == 0 {
+                    return Some(start);
+                }
+                let week_start = monday(start)?.checked_add_days(Days::new(7 * weeks))?;
+                (0..7)
+                    .filter_map(|offset| week_start.checked_add_days(Days::new(offset)))
+                    .find(|day| days.contains(&day.weekday()))?
+            }
+            Frequency::Monthly(day) => {
+                let day = day.unwrap_or(self.anchor.unwrap_or(start).day());
+                let months =
+                    (from.year() - start.year()) as u32 * 12 + from.month() - start.month();
+                let months = months / interval * interval;
+                if months == 0 {
+                    return Some(start);
+                }
+                let month = start.with_day(1)?.checked_add_months(Months::new(months))?;
+                clamped_day(month.year(), month.month(), day)?
+            }
+            Frequency::Yearly => {
+                let anchor = self.anchor.unwrap_or(start);
+                let years = (from.year() - start.year()) / interval as i32 * interval as i32;
+                if years == 0 {
+                    return Some(start);
+                }
+                clamped_day(start.year() + years, anchor.month(), anchor.day())?
+            }
+        };
+        match self.until {
+            Some(until) if resumed > until => None,
+            _ => Some(resumed),
+        }
+    }
+
+    // every occurrence of a series starting at start that falls within [from, to]
+    pub fn occurrences(&self, start: NaiveDate, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        let rule = Recurrence {
+            anchor: self.anchor.or(Some(start)),
+            ..self.clone()
+        };
+        let mut dates = Vec::new();
+        let mut current = match from > start {
+            true => rule.resume_at(start, from),
+            false => Some(start),
+        };
+        while let Some(date) = current {
+            if date > to {
+                break;
+            }
+            if date >= from {
+                dates.push(date);
+            }
+            current = rule.next_after(date);
+        }
+        dates
+    }
+}
+
+impl std::fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self.frequency {
+                Frequency::Daily => "daily",
+                Frequency::Weekly(_) => "weekly",
+                Frequency::Monthly(_) => "monthly",
+                Frequency::Yearly => "yearly",
+            }
+        )?;
+        if self.interval > 1 {
+            write!(f, "/{}", self.interval)?;
+        }
+        match &self.frequency {
+            Frequency::Weekly(days) if !days.is_empty() => {
+                let names: Vec<&str> = days.iter().map(|d| weekday_name(*d)).collect();
+                write!(f, " {}", names.join(","))?;
+            }
+            Frequency::Monthly(Some(day)) => write!(f, " {day}")?,
+            _ => {}
+        }
+        if let Some(until) = self.until {
+            write!(f, " until {}", until.format("%Y%m%d"))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = TaskCommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let first = words.next().ok_or(TaskCommandError::MissingField)?;
+        let (name, interval) = match first.split_once('/') {
+            Some((name, interval)) => (
+                name,
+                interval
+                    .parse::<u32>()
+                    .or(Err(TaskCommandError::NotANumber))?,
+            ),
+            None => (first, 1),
+        };
+        if interval == 0 {
+            return Err(TaskCommandError::InvalidOption);
+        }
+
+        let mut frequency = match name.to_ascii_lowercase().as_str() {
+            "daily" | "day" => Frequency::Daily,
+            "weekly" | "week" => Frequency::Weekly(Vec::new()),
+            "monthly" | "month" => Frequency::Monthly(None),
+            "yearly" | "year" => Frequency::Yearly,
+            _ => return Err(TaskCommandError::InvalidOption),
+        };
+
+        let mut until = None;
+        while let Some(word) = words.next() {
+            match (&mut frequency, word) {
+                (_, "until") => {
+                    let date = words.next().ok_or(TaskCommandError::MissingField)?;
+                    until = Some(parse_date(date).ok_or(TaskCommandError::InvalidOption)?);
+                }
+                (Frequency::Weekly(days), _) => {
+                    for name in word.split(',') {
+                        let day = name
+                            .parse::<Weekday>()
+                            .or(Err(TaskCommandError::InvalidOption))?;
+                        if !days.contains(&day) {
+                            days.push(day);
+                        }
+                    }
+                    days.sort_by_key(|d| d.num_days_from_monday());
+                }
+                (Frequency::Monthly(day), _) => {
+                    let number = word.parse::<u32>().or(Err(TaskCommandError::NotANumber))?;
+                    if !(1..=31).contains(&number) {
+                        return Err(TaskCommandError::InvalidOption);
+                    }
+                    *day = Some(number);
+                }
+                _ => return Err(TaskCommandError::InvalidOption),
+            }
+        }
+
+        Ok(Recurrence {
+            frequency,
+            interval,
+            until,
+            anchor: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tabs::Task;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn series(rule: &str, start: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        let rule: Recurrence = rule.parse().unwrap();
+        rule.occurrences(start, start, to)
+    }
+
+    #[test]
+    fn month_end_does_not_drift() {
+        assert_eq!(
+            series("monthly", date(2025, 1, 31), date(2025, 4, 30)),
+            [
+                date(2025, 1, 31),
+                date(2025, 2, 28),
+                date(2025, 3, 31),
+                date(2025, 4, 30)
+            ]
+        );
+
+        // finishing each occurrence in turn keeps to the same day
+        let mut task = Task {
+            date: date(2025, 1, 31),
+            recurrence: "monthly".parse().ok(),
+            ..Task::default()
+        };
+        let mut dates = Vec::new();
+        for _ in 0..3 {
+            task = task.next_occurrence().unwrap();
+            dates.push(task.date);
+        }
+        assert_eq!(
+            dates,
+            [date(2025, 2, 28), date(2025, 3, 31), date(2025, 4, 30)]
+        );
+    }
+
+    #[test]
+    fn leap_day_comes_back() {
+        assert_eq!(
+            series("yearly", date(2024, 2, 29), date(2028, 12, 31)),
+            [
+                date(2024, 2, 29),
+                date(2025, 2, 28),
+                date(2026, 2, 28),
+                date(2027, 2, 28),
+                date(2028, 2, 29)
+            ]
+        );
+    }
+
+    #[test]
+    fn weekdays_every_other_week() {
+        // 2025-01-06 is a monday
+        assert_eq!(
+            series("weekly/2 mon,thu", date(2025, 1, 6), date(2025, 1, 31)),
+            [
+                date(2025, 1, 6),
+                date(2025, 1, 9),
+                date(2025, 1, 20),
+                date(2025, 1, 23)
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_at_until() {
+        let rule: Recurrence = "daily until 20250103".parse().unwrap();
+        assert_eq!(rule.next_after(date(2025, 1, 2)), Some(date(2025, 1, 3)));
+        assert_eq!(rule.next_after(date(2025, 1, 3)), None);
+        assert_eq!(
+            series(
+                "monthly 15 until 20250401",
+                date(2025, 1, 15),
+                date(2025, 12, 31)
+            ),
+            [date(2025, 1, 15), date(2025, 2, 15), date(2025, 3, 15)]
+        );
+    }
+
+    #[test]
+    fn old_series_are_not_walked_from_the_start() {
+        let start = date(2024, 1, 31);
+        for rule in [
+            "daily/3",
+            "weekly",
+            "weekly/2 mon,thu",
+            "monthly",
+            "monthly/3 30",
+            "yearly/2",
+            "daily until 20250301",
+        ] {
+            let rule: Recurrence = rule.parse().unwrap();
+            let walked = rule.occurrences(start, start, date(2032, 12, 31));
+            for offset in (0..2500).step_by(17) {
+                let from = start + Days::new(offset);
+                let to = from + Days::new(45);
+                let expected: Vec<NaiveDate> = walked
+                    .iter()
+                    .copied()
+                    .filter(|day| (from..=to).contains(day))
+                    .collect();
+                assert_eq!(rule.occurrences(start, from, to), expected, "{rule} {from}");
+            }
+        }
+    }
+
+    #[test]
+    fn same_rule_keeps_its_anchor() {
+        let old = Recurrence {
+            anchor: Some(date(2025, 1, 31)),
+            .."monthly".parse().unwrap()
+        };
+        let mut same: Recurrence = "monthly".parse().unwrap();
+        same.keep_anchor(Some(&old));
+        assert_eq!(same.anchor, old.anchor);
+        let mut other: Recurrence = "monthly/2".parse().unwrap();
+        other.keep_anchor(Some(&old));
+        assert_eq!(other.anchor, None);
+    }
+}
@@ -0,0 +1,500 @@
+use crate::tabs::{Options, TaskCommandError, TaskList};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    env,
+    fs::{self, File, OpenOptions, TryLockError},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{ErrorKind, prelude::*},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+mod sqlite;
+
+pub use sqlite::SqliteStorage;
+
+/*
+    Reading and writing of the task store. Shared by the tui and the
+    command line interface so both always agree on the file layout. The
+    format follows the extension of the file, json unless it is one of
+    SQLITE_EXTENSIONS.
+*/
+
+pub trait Storage {
+    // the lists and settings of a data file, upgraded to the current layout
+    fn load_data(&self, filename: &str) -> Result<DataFile, TaskCommandError>;
+    fn save_data(
+        &self,
+        filename: &str,
+        task_lists: &[TaskList],
+        settings: &Options,
+    ) -> Result<(), TaskCommandError>;
+    // writes task_lists over a file that still holds base, backends that
+    // can write only what changed override it
+    fn save_changes(
+        &self,
+        filename: &str,
+        base: &[TaskList],
+        task_lists: &[TaskList],
+        settings: &Options,
+    ) -> Result<(), TaskCommandError> {
+        let _ = base;
+        self.save_data(filename, task_lists, settings)
+    }
+    // a single list, as written by save_list
+    fn load_list(&self, filename: &str) -> Result<TaskList, TaskCommandError>;
+    fn save_list(&self, filename: &str, task_list: &TaskList) -> Result<(), TaskCommandError>;
+}
+
+pub struct JsonStorage;
+
+pub const SQLITE_EXTENSIONS: [&str; 3] = ["db", "sqlite", "sqlite3"];
+
+// backups are read in the format of the file they were taken of
+pub fn is_sqlite(filename: &str) -> bool {
+    let filename = parse_backup(filename).map_or(filename, |(file, _)| file);
+    Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SQLITE_EXTENSIONS.contains(&ext))
+}
+
+pub fn backend(filename: &str) -> &'static dyn Storage {
+    match is_sqlite(filename) {
+        true => &SqliteStorage,
+        false => &JsonStorage,
+    }
+}
+
+pub fn load_data(filename: &str) -> Result<DataFile, TaskCommandError> {
+    backend(filename).load_data(filename)
+}
+
+// saves every list along with the settings
+pub fn save_data(
+    filename: &str,
+    task_lists: &[TaskList],
+    settings: &Options,
+) -> Result<(), TaskCommandError> {
+    backend(filename).save_data(filename, task_lists, settings)
+}
+
+// saves over a file last read or written as base
+pub fn save_changes(
+    filename: &str,
+    base: &[TaskList],
+    task_lists: &[TaskList],
+    settings: &Options,
+) -> Result<(), TaskCommandError> {
+    backend(filename).save_changes(filename, base, task_lists, settings)
+}
+
+pub fn load_list(filename: &str) -> Result<TaskList, TaskCommandError> {
+    backend(filename).load_list(filename)
+}
+
+pub fn save_list(filename: &str, task_list: &TaskList) -> Result<(), TaskCommandError> {
+    backend(filename).save_list(filename, task_list)
+}
+
+// the same file name in the other format, used by :t convert
+pub fn converted_name(filename: &str) -> String {
+    let extension = match is_sqlite(filename) {
+        true => "json",
+        false => "db",
+    };
+    Path::new(filename)
+        .with_extension(extension)
+        .to_string_lossy()
+        .into_owned()
+}
+
+// layout version written by this build, see MIGRATIONS when changing it
+pub const FORMAT_VERSION: u64 = 1;
+
+// contents of the data file once brought up to FORMAT_VERSION
+#[derive(Default, Deserialize)]
+pub struct DataFile {
+    pub lists: Vec<TaskList>,
+    #[serde(default)]
+    pub settings: Options,
+}
+
+#[derive(Serialize)]
+struct DataFileRef<'a> {
+    version: u64,
+    lists: &'a [TaskList],
+    settings: &'a Options,
+}
+
+/*
+    Upgrades for files written by older versions. The step at index n
+    turns a version n document into a version n + 1 one, so a file is
+    brought up to date by running every step from its own version on.
+*/
+const MIGRATIONS: [fn(Value) -> Value; 1] = [wrap_bare_lists];
+
+// version 0 was a bare array of lists without any settings
+fn wrap_bare_lists(lists: Value) -> Value {
+    json!({ "version": 1, "lists": lists })
+}
+
+// keeps the position serde_json found the problem at, 0 when it has none
+pub fn format_error(error: serde_json::Error) -> TaskCommandError {
+    let message = error.to_string();
+    // the message repeats the position at its end
+    let message = match message.rfind(" at line ") {
+        Some(end) if error.line() > 0 => message[..end].to_string(),
+        _ => message,
+    };
+    TaskCommandError::InvalidFileFormat {
+        line: error.line(),
+        column: error.column(),
+        message,
+    }
+}
+
+fn file_version(document: &Value) -> Result<u64, TaskCommandError> {
+    let problem = match document {
+        Value::Array(_) => return Ok(0),
+        Value::Object(fields) => match fields.get("version") {
+            Some(version) => match version.as_u64() {
+                Some(version) => return Ok(version),
+                None => "version is not a number",
+            },
+            None => "missing field `version`",
+        },
+        _ => "expected the task lists",
+    };
+    Err(TaskCommandError::InvalidFileFormat {
+        line: 0,
+        column: 0,
+        message: problem.to_string(),
+    })
+}
+
+pub const DEFAULT_DATA_FILE: &str = "list.json";
+pub const DEFAULT_SQLITE_FILE: &str = "list.db";
+// overrides the data directory, the --data-dir flag takes precedence
+pub const DATA_DIR_ENV: &str = "CLTODO_DATA_DIR";
+// "sqlite" keeps the tasks in DEFAULT_SQLITE_FILE instead
+pub const STORAGE_ENV: &str = "CLTODO_STORAGE";
+
+fn non_empty_var(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/*
+    Directory holding the task store. Resolved from, in order, the given
+    override, $CLTODO_DATA_DIR, $XDG_DATA_HOME/cltodo and finally
+    ~/.local/share/cltodo. Falls back to the working directory when none
+    of these are set.
+*/
+pub fn data_dir(dir_override: Option<&str>) -> PathBuf {
+    if let Some(dir) = dir_override {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = non_empty_var(DATA_DIR_ENV) {
+        return dir;
+    }
+    // relative values are invalid per the spec and get ignored
+    if let Some(dir) = non_empty_var("XDG_DATA_HOME").filter(|dir| dir.is_absolute()) {
+        return dir.join("cltodo");
+    }
+    match non_empty_var("HOME") {
+        Some(home) => home.join(".local").join("share").join("cltodo"),
+        None => PathBuf::from("."),
+    }
+}
+
+// name of the data file inside the data directory
+pub fn default_data_file() -> &'static str {
+    match env::var(STORAGE_ENV) {
+        Ok(backend) if backend.eq_ignore_ascii_case("sqlite") => DEFAULT_SQLITE_FILE,
+        _ => DEFAULT_DATA_FILE,
+    }
+}
+
+pub fn data_exists(filename: &str) -> bool {
+    Path::new(filename).exists()
+}
+
+// missing files are reported as a bad path, anything else as an i/o error
+pub fn io_error(error: std::io::Error) -> TaskCommandError {
+    match error.kind() {
+        ErrorKind::NotFound | ErrorKind::PermissionDenied => TaskCommandError::InvalidFilePath,
+        _ => TaskCommandError::Io(error.to_string()),
+    }
+}
+
+fn read_file(filename: &str) -> Result<Vec<u8>, TaskCommandError> {
+    fs::read(filename).map_err(io_error)
+}
+
+// held while the app has the data file open, released when dropped
+pub struct DataLock {
+    _file: File,
+}
+
+/*
+    Takes the advisory lock on <file>.lock so a second instance knows the
+    data file is in use. None when another process already holds it.
+*/
+pub fn lock_data(filename: &str) -> Result<Option<DataLock>, TaskCommandError> {
+    let mut lock_name = Path::new(filename).as_os_str().to_owned();
+    lock_name.push(".lock");
+    let lock_path = PathBuf::from(lock_name);
+    if let Some(parent) = lock_path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(io_error)?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(DataLock { _file: file })),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(io_error(e)),
+    }
+}
+
+// what the data file looked like when it was last read or written
+#[derive(Clone, PartialEq)]
+pub struct DiskStamp {
+    modified: Option<SystemTime>,
+    hash: u64,
+}
+
+fn modified(filename: &str) -> Option<SystemTime> {
+    fs::metadata(filename).and_then(|m| m.modified()).ok()
+}
+
+// None when the file does not exist
+pub fn disk_stamp(filename: &str) -> Option<DiskStamp> {
+    let data = fs::read(filename).ok()?;
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    Some(DiskStamp {
+        modified: modified(filename),
+        hash: hasher.finish(),
+    })
+}
+
+/*
+    True when someone else wrote the file since stamp was taken. The
+    contents are only hashed when the modification time moved, so touching
+    the file without changing it does not count.
+*/
+pub fn changed_on_disk(filename: &str, stamp: &Option<DiskStamp>) -> bool {
+    match stamp {
+        None => data_exists(filename),
+        Some(stamp) if stamp.modified.is_some() && stamp.modified == modified(filename) => false,
+        Some(stamp) => disk_stamp(filename).is_some_and(|current| current.hash != stamp.hash),
+    }
+}
+
+/*
+    Writes the data to a temporary file next to the target, flushes it to
+    the disk and renames it over the target so a crash or a full disk never
+    leaves a half written file behind.
+*/
+pub fn write_file(filename: &str, data: &[u8]) -> Result<(), TaskCommandError> {
+    let path = Path::new(filename);
+    // the data directory does not exist until the first save
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    if let Some(dir) = dir {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    let temp_path = PathBuf::from(temp_name);
+
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(io_error(e));
+    }
+
+    // makes the rename itself durable, not supported everywhere
+    if let Ok(dir) = File::open(dir.unwrap_or(Path::new("."))) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn backup_prefix(path: &Path) -> String {
+    format!(
+        "{}.",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    )
+}
+
+/*
+    Backups of a data file, newest first. They live next to it and are
+    named <file>.<yyyymmdd-hhmmss.micros>.bak, older ones without the
+    micros, so they are sorted by the time in the name and not the name.
+*/
+pub fn list_backups(filename: &str) -> Vec<PathBuf> {
+    let path = Path::new(filename);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let prefix = backup_prefix(path);
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut backups: Vec<(Option<NaiveDateTime>, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter_map(|backup| {
+            let name = backup.file_name()?.to_string_lossy().into_owned();
+            let taken = parse_backup(&name).map(|(_, time)| time);
+            (name.starts_with(&prefix) && name.ends_with(".bak")).then_some((taken, backup))
+        })
+        .collect();
+    backups.sort();
+    backups.reverse();
+    backups.into_iter().map(|(_, backup)| backup).collect()
+}
+
+// the file a backup was taken of and when, for names <file>.<stamp>.bak
+fn parse_backup(name: &str) -> Option<(&str, NaiveDateTime)> {
+    let rest = name.strip_suffix(".bak")?;
+    // stamps from before microseconds were added have no fraction
+    let (file, stamp) = rest.rsplit_once('.')?;
+    if let Ok(time) = NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S") {
+        return Some((file, time));
+    }
+    let (file, _) = file.rsplit_once('.')?;
+    let stamp = &rest[file.len() + 1..];
+    let time = NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S%.6f").ok()?;
+    Some((file, time))
+}
+
+// a free name for a backup taken now, a backup within the same
+// microsecond as another moves on to the next free one
+fn backup_name(filename: &str) -> String {
+    let mut time = chrono::offset::Local::now().naive_local();
+    loop {
+        let name = format!("{filename}.{}.bak", time.format("%Y%m%d-%H%M%S%.6f"));
+        if !Path::new(&name).exists() {
+            return name;
+        }
+        time += chrono::TimeDelta::microseconds(1);
+    }
+}
+
+// drops the oldest backups beyond keep
+fn prune_backups(filename: &str, keep: usize) -> Result<(), TaskCommandError> {
+    for old in list_backups(filename).into_iter().skip(keep) {
+        fs::remove_file(old).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+// copies the current data file to a new backup and drops the oldest ones
+// beyond keep, skipped when the file is about to be written unchanged
+fn rotate_backups(filename: &str, data: &[u8], keep: usize) -> Result<(), TaskCommandError> {
+    if keep == 0 {
+        return Ok(());
+    }
+    let current = match fs::read(filename) {
+        Ok(current) if current != data => current,
+        _ => return Ok(()),
+    };
+    write_file(&backup_name(filename), &current)?;
+    prune_backups(filename, keep)
+}
+
+impl Storage for JsonStorage {
+    // reads a data file of any supported version, upgrading it on the way
+    fn load_data(&self, filename: &str) -> Result<DataFile, TaskCommandError> {
+        let data = read_file(filename)?;
+        let mut document: Value = serde_json::from_slice(&data).map_err(format_error)?;
+
+        let version = file_version(&document)?;
+        if version > FORMAT_VERSION {
+            return Err(TaskCommandError::UnsupportedVersion(version));
+        }
+        // current files are parsed again from the text so errors keep their position
+        if version == FORMAT_VERSION {
+            return serde_json::from_slice(&data).map_err(format_error);
+        }
+        for migrate in &MIGRATIONS[version as usize..] {
+            document = migrate(document);
+        }
+        serde_json::from_value(document).map_err(format_error)
+    }
+
+    // rewrites the whole file, keeping settings.backup_count copies of what
+    // was there before
+    fn save_data(
+        &self,
+        filename: &str,
+        task_lists: &[TaskList],
+        settings: &Options,
+    ) -> Result<(), TaskCommandError> {
+        let file = DataFileRef {
+            version: FORMAT_VERSION,
+            lists: task_lists,
+            settings,
+        };
+        let data = serde_json::to_vec(&file).map_err(|e| TaskCommandError::Io(e.to_string()))?;
+        rotate_backups(filename, &data, settings.backup_count)?;
+        write_file(filename, &data)
+    }
+
+    fn load_list(&self, filename: &str) -> Result<TaskList, TaskCommandError> {
+        let data = read_file(filename)?;
+        serde_json::from_slice(&data).map_err(format_error)
+    }
+
+    fn save_list(&self, filename: &str, task_list: &TaskList) -> Result<(), TaskCommandError> {
+        let data =
+            serde_json::to_vec(task_list).map_err(|e| TaskCommandError::Io(e.to_string()))?;
+        write_file(filename, &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backups_sort_by_time_taken() {
+        let dir = env::temp_dir().join(format!("todoapp-backups-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let data_file = dir.join("list.json").to_string_lossy().into_owned();
+        let names = [
+            "list.json.20261017-101009.900000.bak",
+            "list.json.20261017-101010.bak",
+            "list.json.20261017-101010.500000.bak",
+        ];
+        for name in names {
+            fs::write(dir.join(name), "{}").unwrap();
+        }
+
+        let backups: Vec<PathBuf> = names.iter().rev().map(|name| dir.join(name)).collect();
+        assert_eq!(list_backups(&data_file), backups);
+        prune_backups(&data_file, 1).unwrap();
+        assert_eq!(list_backups(&data_file), &backups[..1]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,601 @@
+use super::{DataFile, Storage, backup_name, format_error, io_error, list_backups, prune_backups};
+use crate::{
+    journal::{self, Event},
+    tabs::{Options, Task, TaskCommandError, TaskList},
+};
+use rusqlite::{Connection, ErrorCode, OpenFlags, OptionalExtension, Transaction, params};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    time::Duration,
+};
+
+/*
+    Task store kept in an embedded sqlite database. Every task is a row of
+    its own holding the task as json without its sub-tasks. A save is told
+    the lists as they were last saved and compares them in memory, so only
+    the rows of the tasks that changed are serialized and written. New
+    databases and changes to the lists themselves are written in full.
+
+    Backups are copies of the whole database made with VACUUM INTO, so they
+    are taken at most once every BACKUP_INTERVAL instead of on every save.
+*/
+pub struct SqliteStorage;
+
+// layout version kept in user_version, bump along with a migration
+const SCHEMA_VERSION: u64 = 1;
+
+const BACKUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const SCHEMA: &str = "
+    CREATE TABLE lists (position INTEGER PRIMARY KEY, name TEXT NOT NULL);
+    CREATE TABLE tasks (
+        id INTEGER PRIMARY KEY,
+        list INTEGER NOT NULL,
+        parent INTEGER,
+        position INTEGER NOT NULL,
+        task TEXT NOT NULL
+    );
+    CREATE TABLE settings (id INTEGER PRIMARY KEY CHECK (id = 0), settings TEXT NOT NULL);
+";
+
+// a task as stored: list position, parent id, position among its siblings, json
+#[derive(PartialEq)]
+struct Row {
+    list: i64,
+    parent: Option<i64>,
+    position: i64,
+    task: String,
+}
+
+fn sql_error(error: rusqlite::Error) -> TaskCommandError {
+    match error.sqlite_error_code() {
+        Some(ErrorCode::CannotOpen) => TaskCommandError::InvalidFilePath,
+        Some(ErrorCode::NotADatabase) => TaskCommandError::InvalidFileFormat {
+            line: 0,
+            column: 0,
+            message: "not a task database".to_string(),
+        },
+        _ => TaskCommandError::Io(error.to_string()),
+    }
+}
+
+fn open_existing(filename: &str) -> Result<Connection, TaskCommandError> {
+    let connection = Connection::open_with_flags(filename, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(sql_error)?;
+    let version: u64 = connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(sql_error)?;
+    match version {
+        0 => Err(TaskCommandError::InvalidFileFormat {
+            line: 0,
+            column: 0,
+            message: "not a task database".to_string(),
+        }),
+        SCHEMA_VERSION => Ok(connection),
+        version => Err(TaskCommandError::UnsupportedVersion(version)),
+    }
+}
+
+// creates the tables the first time the file is written, true when it did
+fn open_for_writing(filename: &str) -> Result<(Connection, bool), TaskCommandError> {
+    if let Some(parent) = Path::new(filename).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    let connection = Connection::open(filename).map_err(sql_error)?;
+    let version: u64 = connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(sql_error)?;
+    match version {
+        0 => {
+            connection.execute_batch(SCHEMA).map_err(sql_error)?;
+            connection
+                .pragma_update(None, "user_version", SCHEMA_VERSION)
+                .map_err(sql_error)?;
+            Ok((connection, true))
+        }
+        SCHEMA_VERSION => Ok((connection, false)),
+        version => Err(TaskCommandError::UnsupportedVersion(version)),
+    }
+}
+
+// copies the database unless the newest backup is recent enough
+fn backup(connection: &Connection, filename: &str, keep: usize) -> Result<(), TaskCommandError> {
+    if keep == 0 {
+        return Ok(());
+    }
+    let recent = list_backups(filename)
+        .first()
+        .and_then(|newest| fs::metadata(newest).ok()?.modified().ok())
+        .and_then(|time| time.elapsed().ok())
+        .is_some_and(|age| age < BACKUP_INTERVAL);
+    if recent {
+        return Ok(());
+    }
+    connection
+        .execute("VACUUM INTO ?1", params![backup_name(filename)])
+        .map_err(sql_error)?;
+    prune_backups(filename, keep)
+}
+
+fn row(
+    list: usize,
+    parent: Option<u64>,
+    position: i64,
+    task: &Task,
+) -> Result<Row, TaskCommandError> {
+    let shallow = Task {
+        sub_tasks: Vec::new(),
+        ..task.clone()
+    };
+    Ok(Row {
+        list: list as i64,
+        parent: parent.map(|id| id as i64),
+        position,
+        task: serde_json::to_string(&shallow).map_err(|e| TaskCommandError::Io(e.to_string()))?,
+    })
+}
+
+fn write_row(transaction: &Transaction, id: u64, row: &Row) -> Result<(), TaskCommandError> {
+    transaction
+        .execute(
+            "INSERT OR REPLACE INTO tasks (id, list, parent, position, task)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id as i64, row.list, row.parent, row.position, row.task],
+        )
+        .map_err(sql_error)?;
+    Ok(())
+}
+
+fn rows(task_lists: &[TaskList]) -> Result<HashMap<i64, Row>, TaskCommandError> {
+    fn collect(
+        list: usize,
+        parent: Option<u64>,
+        tasks: &[Task],
+        rows: &mut HashMap<i64, Row>,
+    ) -> Result<(), TaskCommandError> {
+        for (position, task) in tasks.iter().enumerate() {
+            rows.insert(task.id as i64, row(list, parent, position as i64, task)?);
+            collect(list, Some(task.id), &task.sub_tasks, rows)?;
+        }
+        Ok(())
+    }
+
+    let mut rows = HashMap::new();
+    for (list, task_list) in task_lists.iter().enumerate() {
+        collect(list, None, &task_list.tasks, &mut rows)?;
+    }
+    Ok(rows)
+}
+
+/*
+    Writes the changes that lead from base, what the tables hold, to
+    task_lists. Edited tasks only get their json replaced. Where tasks were
+    added or moved to, the siblings from the first changed one on are
+    written again, numbered on from the one before, so rows left in place
+    keep their positions. Rows of deleted tasks are removed.
+*/
+fn write_changes(
+    transaction: &Transaction,
+    base: &[TaskList],
+    task_lists: &[TaskList],
+    events: &[Event],
+) -> Result<(), TaskCommandError> {
+    fn index<'a>(tasks: &'a [Task], out: &mut HashMap<u64, &'a Task>) {
+        for task in tasks {
+            out.insert(task.id, task);
+            index(&task.sub_tasks, out);
+        }
+    }
+    let mut tasks = HashMap::new();
+    for list in task_lists {
+        index(&list.tasks, &mut tasks);
+    }
+    let mut old = HashMap::new();
+    for list in base {
+        index(&list.tasks, &mut old);
+    }
+
+    // spots keyed by parent, or by list for top level tasks
+    let mut spots: HashMap<(Option<u64>, usize), (usize, usize)> = HashMap::new();
+    for event in events {
+        match event {
+            Event::Edited {
+                task: Task { id, .. },
+            }
+            | Event::StatusChanged { id, .. } => {
+                let Some(task) = tasks.get(id) else {
+                    continue;
+                };
+                let json = row(0, None, 0, task)?.task;
+                transaction
+                    .execute(
+                        "UPDATE tasks SET task = ?1 WHERE id = ?2",
+                        params![json, *id as i64],
+                    )
+                    .map_err(sql_error)?;
+            }
+            Event::Created {
+                list,
+                parent,
+                index,
+                ..
+            }
+            | Event::Moved {
+                list,
+                parent,
+                index,
+                ..
+            } => {
+                let key = (*parent, if parent.is_some() { 0 } else { *list });
+                let first = spots.entry(key).or_insert((*list, *index));
+                first.1 = first.1.min(*index);
+            }
+            _ => {}
+        }
+    }
+
+    for id in old.keys().filter(|id| !tasks.contains_key(id)) {
+        transaction
+            .execute("DELETE FROM tasks WHERE id = ?1", params![*id as i64])
+            .map_err(sql_error)?;
+    }
+
+    for ((parent, _), (list, first)) in spots {
+        let siblings = match parent {
+            Some(parent) => tasks.get(&parent).map(|task| &task.sub_tasks[..]),
+            None => task_lists.get(list).map(|list| &list.tasks[..]),
+        };
+        let Some(siblings) = siblings else {
+            continue;
+        };
+        let mut position = match first.checked_sub(1).and_then(|index| siblings.get(index)) {
+            Some(before) => transaction
+                .query_row(
+                    "SELECT position FROM tasks WHERE id = ?1",
+                    params![before.id as i64],
+                    |row| row.get::<_, i64>(0),
+                )
+                .map_err(sql_error)?,
+            None => -1,
+        };
+        for task in siblings.iter().skip(first) {
+            position += 1;
+            write_row(transaction, task.id, &row(list, parent, position, task)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_settings(transaction: &Transaction, settings: &Options) -> Result<(), TaskCommandError> {
+    let settings =
+        serde_json::to_string(settings).map_err(|e| TaskCommandError::Io(e.to_string()))?;
+    transaction
+        .execute(
+            "INSERT OR REPLACE INTO settings (id, settings) VALUES (0, ?1)",
+            params![settings],
+        )
+        .map_err(sql_error)?;
+    Ok(())
+}
+
+// brings the tables in line with the lists, touching only rows that differ
+fn write_lists(transaction: &Transaction, task_lists: &[TaskList]) -> Result<(), TaskCommandError> {
+    transaction
+        .execute("DELETE FROM lists", [])
+        .map_err(sql_error)?;
+    for (position, list) in task_lists.iter().enumerate() {
+        transaction
+            .execute(
+                "INSERT INTO lists (position, name) VALUES (?1, ?2)",
+                params![position as i64, list.name],
+            )
+            .map_err(sql_error)?;
+    }
+
+    let mut stored: HashMap<i64, Row> = HashMap::new();
+    {
+        let mut select = transaction
+            .prepare("SELECT id, list, parent, position, task FROM tasks")
+            .map_err(sql_error)?;
+        let found = select
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    Row {
+                        list: row.get(1)?,
+                        parent: row.get(2)?,
+                        position: row.get(3)?,
+                        task: row.get(4)?,
+                    },
+                ))
+            })
+            .map_err(sql_error)?;
+        for entry in found {
+            let (id, row) = entry.map_err(sql_error)?;
+            stored.insert(id, row);
+        }
+    }
+
+    for (id, row) in rows(task_lists)? {
+        if stored.remove(&id).as_ref() == Some(&row) {
+            continue;
+        }
+        write_row(transaction, id as u64, &row)?;
+    }
+    // whatever is left was deleted
+    for id in stored.keys() {
+        transaction
+            .execute("DELETE FROM tasks WHERE id = ?1", params![id])
+            .map_err(sql_error)?;
+    }
+    Ok(())
+}
+
+fn read_lists(connection: &Connection) -> Result<Vec<TaskList>, TaskCommandError> {
+    let mut lists = Vec::new();
+    let mut list_index = HashMap::new();
+    let mut select = connection
+        .prepare("SELECT position, name FROM lists ORDER BY position")
+        .map_err(sql_error)?;
+    let found = select
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(sql_error)?;
+    for entry in found {
+        let (position, name) = entry.map_err(sql_error)?;
+        list_index.insert(position, lists.len());
+        lists.push(TaskList::new(name, None));
+    }
+
+    let mut tasks = Vec::new();
+    let mut select = connection
+        .prepare("SELECT id, list, parent, task FROM tasks ORDER BY position")
+        .map_err(sql_error)?;
+    let found = select
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(sql_error)?;
+    for entry in found {
+        let (id, list, parent, task) = entry.map_err(sql_error)?;
+        let task: Task = serde_json::from_str(&task).map_err(|e| match format_error(e) {
+            TaskCommandError::InvalidFileFormat { message, .. } => {
+                TaskCommandError::InvalidFileFormat {
+                    line: 0,
+                    column: 0,
+                    message: format!("task {id}: {message}"),
+                }
+            }
+            other => other,
+        })?;
+        tasks.push((list, parent, task));
+    }
+
+    // children are attached to their parent, orphans end up at the top level
+    let ids: HashSet<u64> = tasks.iter().map(|(_, _, task)| task.id).collect();
+    let mut children: HashMap<u64, Vec<Task>> = HashMap::new();
+    let mut top_level = Vec::new();
+    for (list, parent, task) in tasks {
+        match parent.map(|id| id as u64).filter(|id| ids.contains(id)) {
+            Some(parent) => children.entry(parent).or_default().push(task),
+            None => top_level.push((list, task)),
+        }
+    }
+
+    fn attach(task: &mut Task, children: &mut HashMap<u64, Vec<Task>>) {
+        if let Some(sub_tasks) = children.remove(&task.id) {
+            task.sub_tasks = sub_tasks;
+        }
+        for sub_task in task.sub_tasks.iter_mut() {
+            attach(sub_task, children);
+        }
+    }
+
+    for (list, mut task) in top_level {
+        attach(&mut task, &mut children);
+        let index = match list_index.get(&list) {
+            Some(index) => *index,
+            None => {
+                // rows pointing at a missing list keep their tasks anyway
+                list_index.insert(list, lists.len());
+                lists.push(TaskList::new(format!("List {}", list + 1), None));
+                lists.len() - 1
+            }
+        };
+        lists[index].tasks.push(task);
+    }
+    Ok(lists)
+}
+
+impl Storage for SqliteStorage {
+    fn load_data(&self, filename: &str) -> Result<DataFile, TaskCommandError> {
+        let connection = open_existing(filename)?;
+        let lists = read_lists(&connection)?;
+        let settings: Option<String> = connection
+            .query_row("SELECT settings FROM settings WHERE id = 0", [], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(sql_error)?;
+        let settings = match settings {
+            Some(settings) => serde_json::from_str(&settings).map_err(format_error)?,
+            None => Options::default(),
+        };
+        Ok(DataFile { lists, settings })
+    }
+
+    // one transaction, so a failed save leaves the previous state intact
+    fn save_data(
+        &self,
+        filename: &str,
+        task_lists: &[TaskList],
+        settings: &Options,
+    ) -> Result<(), TaskCommandError> {
+        let (mut connection, created) = open_for_writing(filename)?;
+        if !created {
+            backup(&connection, filename, settings.backup_count)?;
+        }
+        let transaction = connection.transaction().map_err(sql_error)?;
+        write_lists(&transaction, task_lists)?;
+        write_settings(&transaction, settings)?;
+        transaction.commit().map_err(sql_error)
+    }
+
+    fn save_changes(
+        &self,
+        filename: &str,
+        base: &[TaskList],
+        task_lists: &[TaskList],
+        settings: &Options,
+    ) -> Result<(), TaskCommandError> {
+        let events = journal::diff(base, task_lists);
+        let (mut connection, created) = open_for_writing(filename)?;
+        if created || events.iter().any(|e| matches!(e, Event::Lists { .. })) {
+            drop(connection);
+            return self.save_data(filename, task_lists, settings);
+        }
+        backup(&connection, filename, settings.backup_count)?;
+        let transaction = connection.transaction().map_err(sql_error)?;
+        write_changes(&transaction, base, task_lists, &events)?;
+        write_settings(&transaction, settings)?;
+        transaction.commit().map_err(sql_error)
+    }
+
+    fn load_list(&self, filename: &str) -> Result<TaskList, TaskCommandError> {
+        let connection = open_existing(filename)?;
+        read_lists(&connection)?
+            .into_iter()
+            .next()
+            .ok_or(TaskCommandError::NotFound)
+    }
+
+    fn save_list(&self, filename: &str, task_list: &TaskList) -> Result<(), TaskCommandError> {
+        let (mut connection, _) = open_for_writing(filename)?;
+        let transaction = connection.transaction().map_err(sql_error)?;
+        write_lists(&transaction, std::slice::from_ref(task_list))?;
+        transaction.commit().map_err(sql_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage, tabs::TaskStatus};
+
+    fn task(id: u64, title: &str, sub_tasks: Vec<Task>) -> Task {
+        Task {
+            id,
+            title: title.to_string(),
+            sub_tasks,
+            ..Task::default()
+        }
+    }
+
+    fn data_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("todoapp-{name}-{}.db", std::process::id()));
+        let data_file = path.to_string_lossy().into_owned();
+        let _ = fs::remove_file(&data_file);
+        data_file
+    }
+
+    fn remove(data_file: &str) {
+        for backup in list_backups(data_file) {
+            fs::remove_file(backup).unwrap();
+        }
+        fs::remove_file(data_file).unwrap();
+    }
+
+    #[test]
+    fn changes_are_written_in_place() {
+        let data_file = data_file("changes");
+        let settings = Options::default();
+        let mut saved = vec![TaskList::new(
+            "Tasks".to_string(),
+            Some(vec![
+                task(
+                    1,
+                    "one",
+                    vec![task(2, "two", vec![task(3, "three", vec![])])],
+                ),
+                task(4, "four", vec![]),
+                task(5, "five", vec![]),
+            ]),
+        )];
+        SqliteStorage
+            .save_data(&data_file, &saved, &settings)
+            .unwrap();
+
+        let steps: [fn(&mut Vec<TaskList>); 5] = [
+            |lists| {
+                lists[0]
+                    .tasks
+                    .insert(0, task(6, "six", vec![task(7, "seven", vec![])]))
+            },
+            |lists| {
+                lists[0].tasks[1].title = "one again".to_string();
+                lists[0].tasks[2].status = TaskStatus::Finished;
+            },
+            // the parent goes, its sub-task moves up and keeps its own
+            |lists| {
+                let one = lists[0].tasks.remove(1);
+                let two = one.sub_tasks.into_iter().next().unwrap();
+                lists[0].tasks.push(two);
+            },
+            |lists| {
+                let five = lists[0].tasks.remove(2);
+                lists[0].tasks[0].sub_tasks.insert(0, five);
+                lists[0].tasks.swap(0, 1);
+            },
+            |lists| lists.push(TaskList::new("Later".to_string(), None)),
+        ];
+        for step in steps {
+            let mut lists = saved.clone();
+            step(&mut lists);
+            SqliteStorage
+                .save_changes(&data_file, &saved, &lists, &settings)
+                .unwrap();
+            let loaded = SqliteStorage.load_data(&data_file).unwrap();
+            assert!(loaded.lists == lists);
+            saved = lists;
+        }
+        remove(&data_file);
+    }
+
+    #[test]
+    fn saves_back_up_the_database() {
+        let data_file = data_file("backup");
+        let settings = Options::default();
+        let first = vec![TaskList::new("Tasks".to_string(), None)];
+        let second = vec![TaskList::new(
+            "Tasks".to_string(),
+            Some(vec![task(1, "one", vec![])]),
+        )];
+        SqliteStorage
+            .save_data(&data_file, &first, &settings)
+            .unwrap();
+        assert!(list_backups(&data_file).is_empty());
+
+        SqliteStorage
+            .save_changes(&data_file, &first, &second, &settings)
+            .unwrap();
+        // too soon for another one
+        SqliteStorage
+            .save_changes(&data_file, &second, &first, &settings)
+            .unwrap();
+        let backups = list_backups(&data_file);
+        assert_eq!(backups.len(), 1);
+        let backup = storage::load_data(&backups[0].to_string_lossy()).unwrap();
+        assert!(backup.lists == first);
+        remove(&data_file);
+    }
+}
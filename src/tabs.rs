@@ -1,9 +1,27 @@
-use crate::{CommandRequest, popup::*, theme::THEME, widgets::Calendar};
-use chrono::{Datelike, NaiveDate};
+use crate::{
+    CommandRequest, dependencies,
+    formats::{self, Format},
+    history::History,
+    journal::{self, Journal},
+    merge,
+    popup::*,
+    recurrence::Recurrence,
+    storage,
+    theme::THEME,
+    widgets::Calendar,
+};
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, TimeDelta, Weekday};
 use crossterm::event::KeyCode;
 use ratatui::{layout::Offset, prelude::*, widgets::*};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::prelude::*, str::Split};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    rc::Rc,
+    str::FromStr,
+    str::Split,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 pub struct Duration {
@@ -44,13 +62,151 @@ pub fn disp_md(date: NaiveDate) -> String {
     )
 }
 
+impl FromStr for Duration {
+    type Err = TaskCommandError;
+
+    // accepts the same dd:hh:mm layout that Display produces, with leading
+    // fields optional so "90" is 90 minutes and "1:30" is 1 hour 30 minutes
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s
+            .rsplit(':')
+            .map(|f| f.parse::<u16>().or(Err(TaskCommandError::NotANumber)));
+        let minutes = fields.next().ok_or(TaskCommandError::MissingField)??;
+        let hours = fields.next().transpose()?.unwrap_or(0);
+        let days = fields.next().transpose()?.unwrap_or(0);
+        if fields.next().is_some() {
+            return Err(TaskCommandError::InvalidOption);
+        }
+
+        let total = minutes as u32 + hours as u32 * 60 + days as u32 * 24 * 60;
+        Ok(Duration {
+            days: u16::try_from(total / (24 * 60)).or(Err(TaskCommandError::InvalidOption))?,
+            hours: (total / 60 % 24) as u8,
+            minutes: (total % 60) as u8,
+        })
+    }
+}
+
+/*
+    Parses the date shorthands accepted by the task editor:
+    +d / -d are offsets from today, mmdd is a date in the current year
+    and yyyymmdd is a full date.
+*/
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    let today = chrono::offset::Local::now().date_naive();
+
+    // +d adds d days to todays date
+    if text.starts_with('+') {
+        today.checked_add_days(Days::new(text.get(1..)?.parse::<u64>().ok()?))
+    // -d adds d days to todays date
+    } else if text.starts_with('-') {
+        today.checked_sub_days(Days::new(text.get(1..)?.parse::<u64>().ok()?))
+    // mmdd sets date to (current_year, mm, dd)
+    } else if text.len() == 4 {
+        NaiveDate::from_ymd_opt(
+            today.year(),
+            text.get(0..2)?.parse::<u32>().ok()?,
+            text.get(2..4)?.parse::<u32>().ok()?,
+        )
+    // yyyymmdd sets date to (yyyy, mm, dd)
+    } else if text.len() == 8 {
+        NaiveDate::from_ymd_opt(
+            text.get(0..4)?.parse::<i32>().ok()?,
+            text.get(4..6)?.parse::<u32>().ok()?,
+            text.get(6..8)?.parse::<u32>().ok()?,
+        )
+    } else {
+        None
+    }
+}
+
+#[derive(Debug)]
 pub enum TaskCommandError {
     UnknownCommand,
     InvalidFilePath,
-    InvalidFileFormat,
+    // line and column are 0 when the problem has no single position
+    InvalidFileFormat {
+        line: usize,
+        column: usize,
+        message: String,
+    },
     NotANumber,
     MissingField,
     InvalidOption,
+    NotFound,
+    DependencyCycle,
+    Blocked,
+    NothingToUndo,
+    NothingToRedo,
+    Io(String),
+    // the data file was written by a newer version of the app
+    UnsupportedVersion(u64),
+    // another instance holds the lock on the data file
+    ReadOnly,
+    // the data file was written by someone else since it was loaded
+    ModifiedOnDisk,
+}
+
+impl TaskCommandError {
+    // process exit code used by the command line interface
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            TaskCommandError::UnknownCommand
+            | TaskCommandError::NotANumber
+            | TaskCommandError::MissingField
+            | TaskCommandError::InvalidOption
+            | TaskCommandError::DependencyCycle
+            | TaskCommandError::Blocked
+            | TaskCommandError::NothingToUndo
+            | TaskCommandError::NothingToRedo => 2,
+            TaskCommandError::InvalidFilePath | TaskCommandError::NotFound => 3,
+            TaskCommandError::InvalidFileFormat { .. }
+            | TaskCommandError::UnsupportedVersion(_) => 4,
+            TaskCommandError::Io(_) | TaskCommandError::ModifiedOnDisk => 5,
+            TaskCommandError::ReadOnly => 6,
+        }
+    }
+}
+
+impl std::fmt::Display for TaskCommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskCommandError::UnknownCommand => write!(f, "Unknown Command"),
+            TaskCommandError::InvalidFilePath => write!(f, "Invalid File Path"),
+            TaskCommandError::InvalidFileFormat {
+                line: 0, message, ..
+            } => write!(f, "Invalid File Format: {message}"),
+            TaskCommandError::InvalidFileFormat {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Invalid File Format (line {line}, column {column}): {message}"
+            ),
+            TaskCommandError::NotANumber => write!(f, "Not A Number"),
+            TaskCommandError::MissingField => write!(f, "Missing Field"),
+            TaskCommandError::InvalidOption => write!(f, "Invalid Option"),
+            TaskCommandError::NotFound => write!(f, "Not Found"),
+            TaskCommandError::DependencyCycle => write!(f, "Dependency Cycle"),
+            TaskCommandError::Blocked => write!(f, "Blocked By Unfinished Tasks"),
+            TaskCommandError::NothingToUndo => write!(f, "Nothing To Undo"),
+            TaskCommandError::NothingToRedo => write!(f, "Nothing To Redo"),
+            TaskCommandError::Io(message) => write!(f, "I/O Error: {message}"),
+            TaskCommandError::UnsupportedVersion(version) => write!(
+                f,
+                "File Version {version} Is Newer Than Supported ({}), Update The App",
+                storage::FORMAT_VERSION
+            ),
+            TaskCommandError::ReadOnly => {
+                write!(
+                    f,
+                    "Data File Is Open In Another Window, Changes Cannot Be Saved"
+                )
+            }
+            TaskCommandError::ModifiedOnDisk => write!(f, "Data File Was Changed On Disk"),
+        }
+    }
 }
 
 #[derive(Default, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
@@ -81,6 +237,16 @@ impl TaskStatus {
         }
     }
 
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "not-started" | "notstarted" | "todo" => Some(TaskStatus::NotStarted),
+            "in-progress" | "inprogress" | "doing" => Some(TaskStatus::InProgress),
+            "finished" | "done" => Some(TaskStatus::Finished),
+            "deleted" => Some(TaskStatus::Deleted),
+            _ => None,
+        }
+    }
+
     pub fn cycle_next(&mut self) {
         *self = match *self {
             TaskStatus::NotStarted => TaskStatus::InProgress,
@@ -91,12 +257,258 @@ impl TaskStatus {
     }
 }
 
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Priority {
+    pub fn get_symbol(&self) -> &str {
+        match self {
+            Priority::None => "",
+            Priority::Low => "!",
+            Priority::Medium => "!!",
+            Priority::High => "!!!",
+            Priority::Critical => "!!!!",
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        match self {
+            Priority::None => "None",
+            Priority::Low => "Low",
+            Priority::Medium => "Medium",
+            Priority::High => "High",
+            Priority::Critical => "Critical",
+        }
+    }
+
+    pub fn get_style(&self) -> Style {
+        match self {
+            Priority::None => THEME.priority.none,
+            Priority::Low => THEME.priority.low,
+            Priority::Medium => THEME.priority.medium,
+            Priority::High => THEME.priority.high,
+            Priority::Critical => THEME.priority.critical,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" | "0" => Some(Priority::None),
+            "low" | "1" => Some(Priority::Low),
+            "medium" | "med" | "2" => Some(Priority::Medium),
+            "high" | "3" => Some(Priority::High),
+            "critical" | "crit" | "4" => Some(Priority::Critical),
+            _ => None,
+        }
+    }
+
+    pub fn raise(&mut self) {
+        *self = match *self {
+            Priority::None => Priority::Low,
+            Priority::Low => Priority::Medium,
+            Priority::Medium => Priority::High,
+            Priority::High | Priority::Critical => Priority::Critical,
+        }
+    }
+
+    pub fn lower(&mut self) {
+        *self = match *self {
+            Priority::None | Priority::Low => Priority::None,
+            Priority::Medium => Priority::Low,
+            Priority::High => Priority::Medium,
+            Priority::Critical => Priority::High,
+        }
+    }
+}
+
+/*
+    Splits "#word" tokens out of a task title. Returns the title without
+    them and the tag names without the leading '#'.
+*/
+pub fn extract_tags(title: &str) -> (String, Vec<String>) {
+    let mut tags = Vec::new();
+    let words: Vec<&str> = title
+        .split(' ')
+        .filter(|word| match word.strip_prefix('#') {
+            Some(tag) if !tag.is_empty() => {
+                tags.push(tag.to_string());
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    (words.join(" ").trim().to_string(), tags)
+}
+
+// narrows the tasks shown in the list view without touching the data
+#[derive(Clone)]
+pub struct TaskFilter {
+    pub tag: String,
+    // name of the list the filter applies to, every list when None. A name
+    // keeps pointing at the same list when lists are added or reordered
+    pub list: Option<String>,
+}
+
+impl TaskFilter {
+    // tasks stay visible when any of their sub-tasks match
+    pub fn matches(&self, task: &Task) -> bool {
+        task.has_tag(&self.tag) || task.sub_tasks.iter().any(|t| self.matches(t))
+    }
+}
+
+/*
+    Every (date, task) pair falling within [from, to], including the
+    future occurrences of recurring tasks. Used by the calendar views.
+*/
+pub fn scheduled_tasks(
+    task_lists: &[TaskList],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Vec<(NaiveDate, &Task)> {
+    fn collect<'a>(
+        tasks: &'a [Task],
+        from: NaiveDate,
+        to: NaiveDate,
+        out: &mut Vec<(NaiveDate, &'a Task)>,
+    ) {
+        for task in tasks {
+            if task.status != TaskStatus::Deleted {
+                match &task.recurrence {
+                    Some(recurrence) => out.extend(
+                        recurrence
+                            .occurrences(task.date, from, to)
+                            .into_iter()
+                            .map(|date| (date, task)),
+                    ),
+                    None if task.date >= from && task.date <= to => out.push((task.date, task)),
+                    None => {}
+                }
+            }
+            collect(&task.sub_tasks, from, to, out);
+        }
+    }
+
+    let mut out = Vec::new();
+    for list in task_lists {
+        collect(&list.tasks, from, to, &mut out);
+    }
+    out.sort_by_key(|(date, _)| *date);
+    out
+}
+
+// inserts the next occurrence after a recurring task that was just finished
+pub fn complete_recurring(siblings: &mut Vec<Task>, index: usize) {
+    if siblings[index].status == TaskStatus::Finished
+        && let Some(next) = siblings[index].next_occurrence()
+    {
+        siblings.insert(index + 1, next);
+    }
+}
+
+static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(1);
+
+// hands out a task id that has not been used by any loaded task
+pub fn next_task_id() -> u64 {
+    NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/*
+    Gives every task without an id (or with an id already used elsewhere)
+    a fresh one and makes sure ids handed out later do not collide with
+    the ones that were loaded.
+*/
+pub fn assign_task_ids(task_lists: &mut [TaskList]) {
+    fn reserve(tasks: &[Task]) {
+        for task in tasks {
+            NEXT_TASK_ID.fetch_max(task.id + 1, Ordering::Relaxed);
+            reserve(&task.sub_tasks);
+        }
+    }
+
+    fn assign(tasks: &mut [Task], seen: &mut HashSet<u64>) {
+        for task in tasks {
+            if task.id == 0 || !seen.insert(task.id) {
+                task.id = next_task_id();
+                seen.insert(task.id);
+            }
+            assign(&mut task.sub_tasks, seen);
+        }
+    }
+
+    for list in task_lists.iter() {
+        reserve(&list.tasks);
+    }
+    let mut seen = HashSet::new();
+    for list in task_lists.iter_mut() {
+        assign(&mut list.tasks, &mut seen);
+    }
+}
+
+// every task has an id of its own
+pub fn ids_are_unique(task_lists: &[TaskList]) -> bool {
+    fn insert(tasks: &[Task], seen: &mut HashSet<u64>) -> bool {
+        tasks
+            .iter()
+            .all(|task| seen.insert(task.id) && insert(&task.sub_tasks, seen))
+    }
+
+    let mut seen = HashSet::new();
+    task_lists.iter().all(|list| insert(&list.tasks, &mut seen))
+}
+
+// finds the list index and path within that list of the task with this id
+pub fn find_task(task_lists: &[TaskList], id: u64) -> Option<(usize, Vec<usize>)> {
+    task_lists
+        .iter()
+        .enumerate()
+        .find_map(|(index, list)| Some((index, list.find_path(id)?)))
+}
+
+pub fn task_by_id_mut(task_lists: &mut [TaskList], id: u64) -> Option<&mut Task> {
+    let (list, path) = find_task(task_lists, id)?;
+    task_lists[list].get_task_mut(&path)
+}
+
+// takes a task out of its list, together with its sub-tasks
+pub fn remove_task(task_lists: &mut [TaskList], id: u64) -> Option<Task> {
+    let (list, path) = find_task(task_lists, id)?;
+    let (siblings, index) = task_lists[list].siblings_mut(&path)?;
+    Some(siblings.remove(index))
+}
+
 #[derive(Default, Clone, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 pub struct Task {
-    pub name: String,
+    #[serde(default)]
+    pub id: u64,
+    // files written before notes existed only have a name
+    #[serde(alias = "name")]
+    pub title: String,
+    #[serde(default)]
+    pub notes: String,
     pub status: TaskStatus,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // ids of the tasks, in any list, that have to be finished first
+    #[serde(default)]
+    pub blocked_by: Vec<u64>,
     pub duration: Duration,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
     pub date: NaiveDate,
+    // when the task was made and finished, only known for imported tasks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished: Option<NaiveDate>,
     pub sub_tasks: Vec<Task>,
 
     #[serde(skip)]
@@ -105,10 +517,29 @@ pub struct Task {
 
 impl PartialEq for Task {
     fn eq(&self, other: &Self) -> bool {
-        if self.name != other.name {
+        self.status == other.status && self.same_details(other) && self.sub_tasks == other.sub_tasks
+    }
+}
+
+impl Task {
+    // everything but the status and the sub-tasks
+    pub fn same_details(&self, other: &Task) -> bool {
+        if self.id != other.id {
+            return false;
+        }
+        if self.title != other.title {
+            return false;
+        }
+        if self.notes != other.notes {
+            return false;
+        }
+        if self.priority != other.priority {
+            return false;
+        }
+        if self.tags != other.tags {
             return false;
         }
-        if self.status != other.status {
+        if self.blocked_by != other.blocked_by {
             return false;
         }
         if self.duration != other.duration {
@@ -117,13 +548,54 @@ impl PartialEq for Task {
         if self.date != other.date {
             return false;
         }
+        if self.recurrence != other.recurrence {
+            return false;
+        }
+        if self.created != other.created || self.finished != other.finished {
+            return false;
+        }
 
-        let mut sorted_self = self.sub_tasks.clone();
-        sorted_self.sort();
-        let mut sorted_other = other.sub_tasks.clone();
-        sorted_other.sort();
+        true
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.trim_start_matches('#');
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /*
+        Called once a task is finished. Moves the recurrence rule onto a
+        fresh copy of the task dated at the next occurrence, sub-tasks
+        included and shifted by the same number of days.
+    */
+    pub fn next_occurrence(&mut self) -> Option<Task> {
+        let mut recurrence = self.recurrence.take()?;
+        // later occurrences keep to the date of the first one
+        recurrence.anchor.get_or_insert(self.date);
+        let date = recurrence.next_after(self.date)?;
+
+        fn renew(task: &mut Task, shift: TimeDelta) {
+            task.id = next_task_id();
+            task.status = TaskStatus::NotStarted;
+            task.finished = None;
+            task.expanded = false;
+            task.date = task.date.checked_add_signed(shift).unwrap_or(task.date);
+            for sub_task in task.sub_tasks.iter_mut() {
+                renew(sub_task, shift);
+            }
+        }
+
+        let mut next = self.clone();
+        renew(&mut next, date.signed_duration_since(self.date));
+        next.recurrence = Some(recurrence);
+        Some(next)
+    }
 
-        sorted_self == sorted_other
+    pub fn add_tag(&mut self, tag: &str) {
+        let tag = tag.trim_start_matches('#');
+        if !tag.is_empty() && !self.has_tag(tag) {
+            self.tags.push(tag.to_string());
+        }
     }
 }
 
@@ -132,8 +604,9 @@ pub struct TaskList {
     pub name: String,
     pub tasks: Vec<Task>,
 
+    // path of the highlighted task, empty when the list has none
     #[serde(skip)]
-    pub selected: usize,
+    pub selected: Vec<usize>,
 }
 
 impl PartialEq for TaskList {
@@ -142,12 +615,7 @@ impl PartialEq for TaskList {
             return false;
         }
 
-        let mut sorted_self = self.tasks.clone();
-        sorted_self.sort();
-        let mut sorted_other = other.tasks.clone();
-        sorted_other.sort();
-
-        sorted_self == sorted_other
+        self.tasks == other.tasks
     }
 }
 
@@ -155,23 +623,216 @@ impl TaskList {
     pub fn new(new_name: String, tasks_new: Option<Vec<Task>>) -> Self {
         TaskList {
             name: new_name,
-            selected: 0,
-            tasks: tasks_new.or(Some(Vec::new())).unwrap(),
+            selected: Vec::new(),
+            tasks: tasks_new.unwrap_or_default(),
         }
     }
 
-    fn next_task(&mut self) {
-        if self.tasks.is_empty() {
-            return;
+    pub fn find_path(&self, id: u64) -> Option<Vec<usize>> {
+        fn search(tasks: &[Task], id: u64, path: &mut Vec<usize>) -> bool {
+            for (index, task) in tasks.iter().enumerate() {
+                path.push(index);
+                if task.id == id || search(&task.sub_tasks, id, path) {
+                    return true;
+                }
+                path.pop();
+            }
+            false
         }
-        self.selected = (self.selected + 1) % self.tasks.len();
+
+        let mut path = Vec::new();
+        search(&self.tasks, id, &mut path).then_some(path)
     }
 
-    fn previous_task(&mut self) {
-        if self.tasks.is_empty() {
-            return;
+    // the vector holding the task at path along with its index in it
+    pub fn siblings_mut(&mut self, path: &[usize]) -> Option<(&mut Vec<Task>, usize)> {
+        let (last, parent) = path.split_last()?;
+        let siblings = match parent.is_empty() {
+            true => &mut self.tasks,
+            false => &mut self.get_task_mut(parent)?.sub_tasks,
+        };
+        (*last < siblings.len()).then_some((siblings, *last))
+    }
+
+    pub fn get_task(&self, path: &[usize]) -> Option<&Task> {
+        let (first, rest) = path.split_first()?;
+        let mut task = self.tasks.get(*first)?;
+        for index in rest {
+            task = task.sub_tasks.get(*index)?;
+        }
+        Some(task)
+    }
+
+    pub fn get_task_mut(&mut self, path: &[usize]) -> Option<&mut Task> {
+        let (first, rest) = path.split_first()?;
+        let mut task = self.tasks.get_mut(*first)?;
+        for index in rest {
+            task = task.sub_tasks.get_mut(*index)?;
+        }
+        Some(task)
+    }
+
+    /*
+        Paths of the rows shown in the list view, in display order. Children
+        are only shown below expanded tasks and tasks hidden by the filter
+        are left out at every depth.
+    */
+    pub fn visible_paths(&self, filter: Option<&TaskFilter>) -> Vec<Vec<usize>> {
+        fn collect(
+            tasks: &[Task],
+            filter: Option<&TaskFilter>,
+            path: &mut Vec<usize>,
+            out: &mut Vec<Vec<usize>>,
+        ) {
+            for (index, task) in tasks.iter().enumerate() {
+                if filter.is_some_and(|f| !f.matches(task)) {
+                    continue;
+                }
+                path.push(index);
+                out.push(path.clone());
+                if task.expanded {
+                    collect(&task.sub_tasks, filter, path, out);
+                }
+                path.pop();
+            }
+        }
+
+        let mut out = Vec::new();
+        collect(&self.tasks, filter, &mut Vec::new(), &mut out);
+        out
+    }
+
+    // skips over tasks hidden by the filter or inside collapsed ones
+    fn next_task(&mut self, filter: Option<&TaskFilter>) {
+        let rows = self.visible_paths(filter);
+        if let Some(first) = rows.first() {
+            self.selected = match rows.iter().position(|path| *path == self.selected) {
+                Some(row) => rows[(row + 1) % rows.len()].clone(),
+                None => first.clone(),
+            };
+        }
+    }
+
+    fn previous_task(&mut self, filter: Option<&TaskFilter>) {
+        let rows = self.visible_paths(filter);
+        if let Some(first) = rows.first() {
+            self.selected = match rows.iter().position(|path| *path == self.selected) {
+                Some(row) => rows[(row + rows.len() - 1) % rows.len()].clone(),
+                None => first.clone(),
+            };
+        }
+    }
+
+    // makes the task at path the last child of the sibling above it,
+    // returning its new path
+    fn indent(&mut self, path: &[usize]) -> Option<Vec<usize>> {
+        let (siblings, index) = self.siblings_mut(path)?;
+        if index == 0 {
+            return None;
+        }
+        let task = siblings.remove(index);
+        let parent = &mut siblings[index - 1];
+        parent.sub_tasks.push(task);
+        parent.expanded = true;
+
+        let mut new_path = path.to_vec();
+        *new_path.last_mut()? = index - 1;
+        new_path.push(parent.sub_tasks.len() - 1);
+        Some(new_path)
+    }
+
+    // moves the task at path out of its parent to just below it,
+    // returning its new path
+    fn outdent(&mut self, path: &[usize]) -> Option<Vec<usize>> {
+        let parent_path = &path[..path.len().checked_sub(1)?];
+        if parent_path.is_empty() {
+            return None;
+        }
+        let task = self
+            .get_task_mut(parent_path)?
+            .sub_tasks
+            .remove(*path.last()?);
+        let (siblings, index) = self.siblings_mut(parent_path)?;
+        siblings.insert(index + 1, task);
+
+        let mut new_path = parent_path.to_vec();
+        *new_path.last_mut()? = index + 1;
+        Some(new_path)
+    }
+}
+
+/*
+    What is on screen in the task list tab, by list name and task id so it
+    survives the lists being replaced by a newer copy of the data file.
+*/
+struct ViewState {
+    list: Option<String>,
+    // highlighted task of every list
+    selected: HashMap<String, u64>,
+    expanded: HashSet<u64>,
+}
+
+impl ViewState {
+    fn capture(selected: usize, task_lists: &[TaskList]) -> ViewState {
+        fn expanded(tasks: &[Task], ids: &mut HashSet<u64>) {
+            for task in tasks {
+                if task.expanded {
+                    ids.insert(task.id);
+                }
+                expanded(&task.sub_tasks, ids);
+            }
+        }
+
+        let mut ids = HashSet::new();
+        for list in task_lists {
+            expanded(&list.tasks, &mut ids);
+        }
+        ViewState {
+            list: task_lists.get(selected).map(|list| list.name.clone()),
+            selected: task_lists
+                .iter()
+                .filter_map(|list| Some((list.name.clone(), list.get_task(&list.selected)?.id)))
+                .collect(),
+            expanded: ids,
         }
-        self.selected = (self.selected + self.tasks.len() - 1) % self.tasks.len();
+    }
+
+    // returns the index of the list that was selected, or 0 when it is gone
+    fn restore(&self, task_lists: &mut [TaskList]) -> usize {
+        fn expand(tasks: &mut [Task], ids: &HashSet<u64>) {
+            for task in tasks {
+                task.expanded = ids.contains(&task.id);
+                expand(&mut task.sub_tasks, ids);
+            }
+        }
+
+        for list in task_lists.iter_mut() {
+            expand(&mut list.tasks, &self.expanded);
+            let Some(path) = self
+                .selected
+                .get(&list.name)
+                .and_then(|id| list.find_path(*id))
+            else {
+                continue;
+            };
+            for depth in 1..path.len() {
+                if let Some(parent) = list.get_task_mut(&path[..depth]) {
+                    parent.expanded = true;
+                }
+            }
+            list.selected = path;
+        }
+        self.list
+            .as_ref()
+            .and_then(|name| task_lists.iter().position(|list| &list.name == name))
+            .unwrap_or(0)
+    }
+}
+
+// a store without lists still gets one to add tasks to
+pub fn ensure_a_list(task_lists: &mut Vec<TaskList>) {
+    if task_lists.is_empty() {
+        task_lists.push(TaskList::new("Tasks".to_string(), None));
     }
 }
 
@@ -182,33 +843,102 @@ pub struct TaskListTab {
     pub new_task_window: TaskEditorPopup,
     pub delete_conf_window: ConfirmationPopup,
     pub new_tasklist_window: TextEntryPopup,
-
-    pub selected_sub_task: usize,
+    pub changes_window: ChoicePopup,
+    pub import_window: ChoicePopup,
+
+    pub data_file: String,
+    // where exported lists are written
+    pub data_dir: PathBuf,
+    // the lists as last read or written, the base when merging
+    pub saved_lists: Vec<TaskList>,
+    pub saved_revision: u64,
+    pub saved_at: Option<DateTime<Local>>,
+    // None when another instance has the data file, which makes it read-only
+    pub lock: Option<storage::DataLock>,
+    // the data file as last loaded or saved, to notice writes by others
+    pub disk_stamp: Option<storage::DiskStamp>,
+    // every saved change is appended to it
+    pub journal: Journal,
+    pub filter: Option<TaskFilter>,
+    // set when a key press fails, picked up and shown by the app
+    pub error: Option<TaskCommandError>,
+    pub history: History,
 }
 
 impl TaskListTab {
     pub fn handle_input(&mut self, task_lists: &mut Vec<TaskList>, key: KeyCode) -> bool {
-        let selected_list = &mut task_lists[self.selected];
+        let filter = task_lists
+            .get(self.selected)
+            .and_then(|list| self.active_filter(list))
+            .cloned();
+        let filter = filter.as_ref();
         let mut input_captured = true;
 
         if PopupStatus::InUse == self.new_task_window.status {
             input_captured = self.new_task_window.handle_input(key);
+            if let Some(e) = self.new_task_window.error.take() {
+                self.error = Some(e);
+            }
             // recheck status so new task can be added on the same frame
             if PopupStatus::Confirmed == self.new_task_window.status {
+                self.checkpoint(task_lists);
                 match self.new_task_window.task_source {
-                    TaskSource::New => selected_list.tasks.push(self.new_task_window.take_task()),
+                    TaskSource::New => {
+                        let mut task = self.new_task_window.take_task();
+                        task.id = next_task_id();
+                        if let Some(list) = task_lists.get_mut(self.selected) {
+                            let tasks = &mut list.tasks;
+                            tasks.push(task);
+                            complete_recurring(tasks, tasks.len() - 1);
+                        }
+                    }
+                    TaskSource::Child => {
+                        let mut task = self.new_task_window.take_task();
+                        task.id = next_task_id();
+                        if let Some(selected_list) = task_lists.get_mut(self.selected) {
+                            match self.selected_path(selected_list) {
+                                Some(mut path) => {
+                                    let parent = selected_list.get_task_mut(&path).unwrap();
+                                    let index = parent.sub_tasks.len();
+                                    parent.sub_tasks.push(task);
+                                    complete_recurring(&mut parent.sub_tasks, index);
+                                    parent.expanded = true;
+                                    path.push(index);
+                                    selected_list.selected = path;
+                                }
+                                None => {
+                                    selected_list.tasks.push(task);
+                                    let index = selected_list.tasks.len() - 1;
+                                    complete_recurring(&mut selected_list.tasks, index);
+                                }
+                            }
+                        }
+                    }
                     TaskSource::Existing => {
-                        let task = &mut selected_list.tasks[selected_list.selected];
-                        if task.sub_tasks.len() > 0 && task.expanded && self.selected_sub_task != 0
+                        let mut task = self.new_task_window.take_task();
+                        let index = dependencies::task_index(task_lists);
+                        let previous = index.get(&task.id).map(|t| t.status.clone());
+                        if task.status == TaskStatus::Finished
+                            && previous != Some(TaskStatus::Finished)
+                            && dependencies::is_blocked(&index, &task)
+                        {
+                            // keep the other edits but not the completion
+                            task.status = previous.unwrap_or_default();
+                            self.error = Some(TaskCommandError::Blocked);
+                        }
+
+                        if let Some(selected_list) = task_lists.get_mut(self.selected)
+                            && let Some(path) = self.selected_path(selected_list)
+                            && let Some((siblings, index)) = selected_list.siblings_mut(&path)
                         {
-                            task.sub_tasks[self.selected_sub_task - 1] =
-                                self.new_task_window.take_task();
-                        } else {
-                            *task = self.new_task_window.take_task();
+                            siblings[index] = task;
+                            complete_recurring(siblings, index);
                         }
                     }
                 }
                 self.new_task_window.status = PopupStatus::Closed;
+                debug_assert!(ids_are_unique(task_lists));
+                self.reveal_selection(task_lists);
             }
         } else if self.delete_conf_window.status == PopupStatus::InUse {
             input_captured = self.delete_conf_window.handle_input(key);
@@ -225,69 +955,71 @@ impl TaskListTab {
                     self.delete_conf_window.close();
                 }
             }
+        } else if self.changes_window.status == PopupStatus::InUse {
+            input_captured = self.changes_window.handle_input(key);
+            if self.changes_window.status != PopupStatus::InUse {
+                self.changes_window.close();
+            }
+        } else if self.import_window.status == PopupStatus::InUse {
+            input_captured = self.import_window.handle_input(key);
+            if self.import_window.status != PopupStatus::InUse {
+                self.import_window.close();
+            }
         } else if self.new_tasklist_window.status == PopupStatus::InUse {
             input_captured = self.new_tasklist_window.handle_input(key);
 
             match self.new_tasklist_window.status {
                 PopupStatus::InUse | PopupStatus::Closed => {}
                 PopupStatus::Canceled => self.new_tasklist_window.reset(),
-                PopupStatus::Confirmed => task_lists.push(TaskList {
-                    name: self.new_tasklist_window.take(),
-                    selected: 0,
-                    tasks: Vec::new(),
-                }),
+                PopupStatus::Confirmed => {
+                    self.checkpoint(task_lists);
+                    task_lists.push(TaskList::new(self.new_tasklist_window.take(), None));
+                }
             }
         } else {
             match key {
                 KeyCode::Char('h') => self.previous_tab(task_lists),
                 KeyCode::Char('l') => self.next_tab(task_lists),
                 KeyCode::Char('k') => {
-                    if selected_list.tasks.len() > 0 {
-                        if selected_list.tasks[selected_list.selected].expanded {
-                            if self.selected_sub_task == 0 {
-                                selected_list.previous_task();
-                                if selected_list.tasks[selected_list.selected].expanded {
-                                    self.selected_sub_task =
-                                        selected_list.tasks[selected_list.selected].sub_tasks.len();
-                                }
-                            } else {
-                                self.selected_sub_task -= 1;
-                            }
-                        } else {
-                            selected_list.previous_task();
-                            if selected_list.tasks[selected_list.selected].expanded {
-                                self.selected_sub_task =
-                                    selected_list.tasks[selected_list.selected].sub_tasks.len();
-                            }
-                        }
+                    if let Some(list) = task_lists.get_mut(self.selected) {
+                        list.previous_task(filter);
                     }
                 }
                 KeyCode::Char('j') => {
-                    if selected_list.tasks.len() > 0 {
-                        if selected_list.tasks[selected_list.selected].expanded {
-                            self.selected_sub_task += 1;
-                            if self.selected_sub_task
-                                > selected_list.tasks[selected_list.selected].sub_tasks.len()
-                            {
-                                self.selected_sub_task = 0;
-                                selected_list.next_task();
-                            }
-                        } else {
-                            selected_list.next_task();
-                        }
+                    if let Some(list) = task_lists.get_mut(self.selected) {
+                        list.next_task(filter);
                     }
                 }
                 KeyCode::Char('a') => self.new_task(),
+                KeyCode::Char('A') => self.new_child_task(task_lists),
                 KeyCode::Char('e') => self.edit_task(task_lists),
                 KeyCode::Char('m') => self.mark_task(task_lists),
+                KeyCode::Char('+') => self.change_task(task_lists, |t| t.priority.raise()),
+                KeyCode::Char('-') => self.change_task(task_lists, |t| t.priority.lower()),
+                KeyCode::Char('u') => {
+                    if let Err(e) = self.undo(task_lists) {
+                        self.error = Some(e);
+                    }
+                }
                 KeyCode::Char('d') => self.try_delete_task(task_lists),
+                KeyCode::Char('>') => self.move_task(task_lists, TaskList::indent),
+                KeyCode::Char('<') => self.move_task(task_lists, TaskList::outdent),
                 KeyCode::Right => {
-                    let task = &mut selected_list.tasks[selected_list.selected];
-                    if task.expanded {
-                        task.expanded = false;
-                        self.selected_sub_task = 0;
-                    } else {
-                        task.expanded = true;
+                    if let Some(task) = self.selected_task_mut(task_lists) {
+                        task.expanded = !task.expanded;
+                    }
+                }
+                // collapses the selected task, or moves up to its parent
+                KeyCode::Left => {
+                    if let Some(selected_list) = task_lists.get_mut(self.selected)
+                        && let Some(path) = self.selected_path(selected_list)
+                    {
+                        let task = selected_list.get_task_mut(&path).unwrap();
+                        if task.expanded && !task.sub_tasks.is_empty() {
+                            task.expanded = false;
+                        } else if path.len() > 1 {
+                            selected_list.selected.pop();
+                        }
                     }
                 }
                 //KeyCode::Char('s') => self.save_data(task_lists),
@@ -303,6 +1035,7 @@ impl TaskListTab {
         &mut self,
         mut command: Split<char>,
         task_lists: &mut Vec<TaskList>,
+        options: &mut Options,
     ) -> Result<CommandRequest, TaskCommandError> {
         match command.next() {
             Some("new") => {
@@ -314,25 +1047,85 @@ impl TaskListTab {
                 Ok(CommandRequest::None)
             }
             Some("sort") => {
-                match command.next() {
-                    Some("name") | None => task_lists[self.selected]
-                        .tasks
-                        .sort_by(|a, b| a.name.cmp(&b.name)),
-                    Some("date") => task_lists[self.selected]
-                        .tasks
-                        .sort_by(|a, b| a.date.cmp(&b.date)),
+                let sort: fn(&mut Vec<Task>) = match command.next() {
+                    Some("name" | "title") | None => {
+                        |tasks| tasks.sort_by(|a, b| a.title.cmp(&b.title))
+                    }
+                    Some("date") => |tasks| tasks.sort_by_key(|t| t.date),
+                    Some("priority") => {
+                        |tasks| tasks.sort_by_key(|t| std::cmp::Reverse(t.priority))
+                    }
                     Some(_) => return Err(TaskCommandError::InvalidOption),
                 };
+                if task_lists.get(self.selected).is_none() {
+                    return Err(TaskCommandError::NotFound);
+                }
+                self.checkpoint(task_lists);
+                sort(&mut task_lists[self.selected].tasks);
                 Ok(CommandRequest::None)
             }
+            Some("filter") => {
+                match command.next() {
+                    None | Some("clear") => self.filter = None,
+                    Some(arg) => {
+                        let tag = arg
+                            .strip_prefix("tag:")
+                            .ok_or(TaskCommandError::InvalidOption)?;
+                        if tag.is_empty() {
+                            return Err(TaskCommandError::MissingField);
+                        }
+                        self.filter = Some(TaskFilter {
+                            tag: tag.to_string(),
+                            list: match command.next() {
+                                Some("all") => None,
+                                None => task_lists.get(self.selected).map(|l| l.name.clone()),
+                                Some(_) => return Err(TaskCommandError::InvalidOption),
+                            },
+                        });
+                        self.reveal_selection(task_lists);
+                    }
+                }
+                Ok(CommandRequest::SetActive)
+            }
             Some("save") => match command.next() {
-                Some(filename) => self.save_data(filename, task_lists),
-                None => self.save_data("list.json", task_lists),
+                Some(filename) => self.save_data(filename, task_lists, options),
+                None => self.save(task_lists, options).map(|_| CommandRequest::None),
+            },
+            // writes the lists in the other format, next to the data file by default
+            Some("convert") => {
+                let filename = match command.next() {
+                    Some(filename) => filename.to_string(),
+                    None => storage::converted_name(&self.data_file),
+                };
+                // a file of the same format would just be a copy
+                if storage::is_sqlite(&filename) == storage::is_sqlite(&self.data_file) {
+                    return Err(TaskCommandError::InvalidOption);
+                }
+                self.save_data(&filename, task_lists, options)
+            }
+            Some("changes") => {
+                let since = match command.next() {
+                    Some(date) => parse_date(date).ok_or(TaskCommandError::InvalidOption)?,
+                    None => parse_date("-1").ok_or(TaskCommandError::InvalidOption)?,
+                };
+                self.show_changes(since)?;
+                Ok(CommandRequest::SetActive)
+            }
+            Some("compact") => {
+                self.journal.compact(Local::now())?;
+                Ok(CommandRequest::None)
+            }
+            Some("restore") => match command.next() {
+                Some(backup) => self.restore_backup(backup, task_lists, options),
+                None => Err(TaskCommandError::MissingField),
             },
             Some("load") => match command.next() {
-                Some(filename) => self.load_data(filename, task_lists),
-                None => self.load_data("list.json", task_lists),
+                Some(filename) => self.load_data(filename, task_lists, options),
+                None => self
+                    .reload(task_lists, options)
+                    .map(|_| CommandRequest::None),
             },
+            Some("lock") => self.relock().map(|_| CommandRequest::None),
             Some("import") => match command.next() {
                 Some(filename) => self.load_list(filename, task_lists),
                 None => Err(TaskCommandError::MissingField),
@@ -342,7 +1135,52 @@ impl TaskListTab {
                     let list_index = num_str
                         .parse::<usize>()
                         .or(Err(TaskCommandError::NotANumber))?;
-                    self.save_list(&task_lists[list_index])
+                    self.save_list(
+                        task_lists
+                            .get(list_index)
+                            .ok_or(TaskCommandError::NotFound)?,
+                    )
+                }
+                None => Err(TaskCommandError::MissingField),
+            },
+            Some(name) if name.starts_with("import-") || name.starts_with("export-") => {
+                self.exchange(name, command, task_lists)
+            }
+            Some("block") => match command.next() {
+                Some(id_str) => {
+                    let blocker = id_str
+                        .parse::<u64>()
+                        .or(Err(TaskCommandError::NotANumber))?;
+                    let task = self.selected_id(task_lists)?;
+                    let snapshot = task_lists.to_vec();
+                    dependencies::add_blocker(task_lists, task, blocker)?;
+                    self.push_snapshot(snapshot);
+                    Ok(CommandRequest::None)
+                }
+                None => Err(TaskCommandError::MissingField),
+            },
+            Some("unblock") => {
+                let blocker = match command.next() {
+                    Some(id_str) => Some(
+                        id_str
+                            .parse::<u64>()
+                            .or(Err(TaskCommandError::NotANumber))?,
+                    ),
+                    None => None,
+                };
+                let task = self.selected_id(task_lists)?;
+                let snapshot = task_lists.to_vec();
+                dependencies::remove_blocker(task_lists, task, blocker)?;
+                self.push_snapshot(snapshot);
+                Ok(CommandRequest::None)
+            }
+            Some("goto") => match command.next() {
+                Some(id_str) => {
+                    let id = id_str
+                        .parse::<u64>()
+                        .or(Err(TaskCommandError::NotANumber))?;
+                    self.goto_task(task_lists, id)?;
+                    Ok(CommandRequest::SetActive)
                 }
                 None => Err(TaskCommandError::MissingField),
             },
@@ -351,22 +1189,24 @@ impl TaskListTab {
         }
     }
 
+    // replaces every list and the settings with the ones in the file
     fn load_data(
         &mut self,
         filename: &str,
         task_lists: &mut Vec<TaskList>,
+        options: &mut Options,
     ) -> Result<CommandRequest, TaskCommandError> {
-        let mut file = match File::open(filename) {
-            Ok(f) => f,
-            Err(_) => return Err(TaskCommandError::InvalidFilePath),
-        };
-        let mut data = Vec::new();
-        _ = file.read_to_end(&mut data).unwrap();
-        let temp: Vec<TaskList> = match serde_json::from_slice(&data) {
-            Ok(v) => v,
-            Err(_) => return Err(TaskCommandError::InvalidFileFormat),
-        };
-        *task_lists = temp;
+        let loaded = storage::load_data(filename)?;
+        self.checkpoint(task_lists);
+        *task_lists = loaded.lists;
+        ensure_a_list(task_lists);
+        *options = loaded.settings;
+        // undoing the load keeps these settings
+        self.history.changed();
+        self.history.set_depth(options.history_depth);
+        assign_task_ids(task_lists);
+        self.selected = 0;
+        self.restore_selection(task_lists);
         Ok(CommandRequest::None)
     }
 
@@ -378,100 +1218,425 @@ impl TaskListTab {
         filename: &str,
         task_lists: &mut Vec<TaskList>,
     ) -> Result<CommandRequest, TaskCommandError> {
-        let mut file = match File::open(filename) {
-            Ok(f) => f,
-            Err(_) => return Err(TaskCommandError::InvalidFilePath),
-        };
-        let mut data = Vec::new();
-        _ = file.read_to_end(&mut data).unwrap();
-        let temp: TaskList = match serde_json::from_slice(&data) {
-            Ok(v) => v,
-            Err(_) => return Err(TaskCommandError::InvalidFileFormat),
-        };
-        task_lists.push(temp);
+        let loaded = storage::load_list(filename)?;
+        self.checkpoint(task_lists);
+        task_lists.push(loaded);
+        assign_task_ids(task_lists);
         Ok(CommandRequest::None)
     }
 
     fn save_data(
         &mut self,
         filename: &str,
+        task_lists: &[TaskList],
+        options: &Options,
+    ) -> Result<CommandRequest, TaskCommandError> {
+        storage::save_data(filename, task_lists, options)?;
+        Ok(CommandRequest::None)
+    }
+
+    // writes every list and the settings to the data file
+    // refuses to save over changes made to the data file by someone else
+    pub fn save(
+        &mut self,
+        task_lists: &[TaskList],
+        options: &Options,
+    ) -> Result<(), TaskCommandError> {
+        if self.lock.is_some() && storage::changed_on_disk(&self.data_file, &self.disk_stamp) {
+            return Err(TaskCommandError::ModifiedOnDisk);
+        }
+        self.overwrite(task_lists, options)
+    }
+
+    pub fn overwrite(
+        &mut self,
+        task_lists: &[TaskList],
+        options: &Options,
+    ) -> Result<(), TaskCommandError> {
+        if self.lock.is_none() {
+            return Err(TaskCommandError::ReadOnly);
+        }
+        // only what changed since the last save when the file still holds it
+        match storage::changed_on_disk(&self.data_file, &self.disk_stamp) {
+            true => storage::save_data(&self.data_file, task_lists, options)?,
+            false => {
+                storage::save_changes(&self.data_file, &self.saved_lists, task_lists, options)?
+            }
+        }
+        let recorded = self.journal.record(task_lists);
+        self.mark_saved(task_lists, Some(Local::now()));
+        recorded
+    }
+
+    // lists the saved changes made since the start of the given day
+    fn show_changes(&mut self, since: NaiveDate) -> Result<(), TaskCommandError> {
+        let entries = journal::read(&journal::journal_path(&self.data_file))?;
+        let since = journal::start_of_day(since).ok_or(TaskCommandError::InvalidOption)?;
+        let mut lines = journal::changes_since(&entries, since);
+
+        // only the latest ones fit on screen
+        const SHOWN: usize = 15;
+        if lines.len() > SHOWN {
+            let hidden = lines.len() - SHOWN;
+            lines.drain(..hidden);
+            lines.insert(0, format!("... {hidden} earlier changes"));
+        }
+        if lines.is_empty() {
+            lines.push("No changes".to_string());
+        }
+        self.changes_window.title = format!("Changes Since {}", since.format("%Y-%m-%d"));
+        self.changes_window
+            .show(lines.join("\n"), vec!["OK".to_string()]);
+        Ok(())
+    }
+
+    // replaces the lists with the data file, keeping what is on screen
+    pub fn reload(
+        &mut self,
+        task_lists: &mut Vec<TaskList>,
+        options: &mut Options,
+    ) -> Result<(), TaskCommandError> {
+        let view = ViewState::capture(self.selected, task_lists);
+        self.load_data(&self.data_file.clone(), task_lists, options)?;
+        self.mark_saved(task_lists, None);
+        self.restore_view(task_lists, &view);
+        Ok(())
+    }
+
+    // applies the changes made since the last save on top of the data file
+    pub fn merge_from_disk(
+        &mut self,
+        task_lists: &mut Vec<TaskList>,
+        options: &Options,
+    ) -> Result<(), TaskCommandError> {
+        let view = ViewState::capture(self.selected, task_lists);
+        let theirs = storage::load_data(&self.data_file)?;
+        let merged = merge::merge(&self.saved_lists, task_lists, theirs.lists);
+        self.checkpoint(task_lists);
+        *task_lists = merged;
+        ensure_a_list(task_lists);
+        self.restore_view(task_lists, &view);
+        self.overwrite(task_lists, options)
+    }
+
+    fn restore_view(&mut self, task_lists: &mut [TaskList], view: &ViewState) {
+        self.selected = view.restore(task_lists);
+        self.restore_selection(task_lists);
+    }
+
+    // tries again to take the lock, for when the other window was closed
+    fn relock(&mut self) -> Result<(), TaskCommandError> {
+        if self.lock.is_none() {
+            self.lock = storage::lock_data(&self.data_file)?;
+        }
+        match self.lock {
+            Some(_) => Ok(()),
+            None => Err(TaskCommandError::ReadOnly),
+        }
+    }
+
+    // remembers the lists as matching the data file
+    pub fn mark_saved(&mut self, task_lists: &[TaskList], time: Option<DateTime<Local>>) {
+        self.disk_stamp = storage::disk_stamp(&self.data_file);
+        self.saved_lists = task_lists.to_vec();
+        self.saved_revision = self.history.revision();
+        self.journal.reset(task_lists);
+        if time.is_some() {
+            self.saved_at = time;
+        }
+    }
+
+    // true when something changed since the data file was last read or written
+    pub fn is_unsaved(&self) -> bool {
+        self.history.revision() != self.saved_revision
+    }
+
+    /*
+        Replaces the lists with the contents of a backup of the data file,
+        given by its position (1 is the newest) or its file name. The data
+        file itself is only changed by the next save.
+    */
+    fn restore_backup(
+        &mut self,
+        backup: &str,
         task_lists: &mut Vec<TaskList>,
+        options: &mut Options,
     ) -> Result<CommandRequest, TaskCommandError> {
-        let mut file = match File::create(filename) {
-            Ok(f) => f,
-            Err(_) => return Err(TaskCommandError::InvalidFilePath),
+        let backups = storage::list_backups(&self.data_file);
+        let path = match backup.parse::<usize>() {
+            Ok(number) => backups
+                .get(number.checked_sub(1).ok_or(TaskCommandError::NotFound)?)
+                .ok_or(TaskCommandError::NotFound)?,
+            Err(_) => backups
+                .iter()
+                .find(|path| path.file_name().is_some_and(|name| name == backup))
+                .ok_or(TaskCommandError::NotFound)?,
         };
-        let out = serde_json::to_vec(&task_lists).unwrap();
-        _ = file.write_all(&out).unwrap();
-        Ok(CommandRequest::None)
+        self.load_data(&path.to_string_lossy(), task_lists, options)
+    }
+
+    // loads the newest backup that can still be read, returning its name
+    pub fn restore_latest_backup(
+        &mut self,
+        task_lists: &mut Vec<TaskList>,
+        options: &mut Options,
+    ) -> Result<String, TaskCommandError> {
+        for path in storage::list_backups(&self.data_file) {
+            if self
+                .load_data(&path.to_string_lossy(), task_lists, options)
+                .is_ok()
+            {
+                return Ok(path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default());
+            }
+        }
+        Err(TaskCommandError::NotFound)
+    }
+
+    /*
+        import-<format> <file> adds the lists read from another program's
+        file, export-<format> <list|all> <file> writes lists in its format.
+    */
+    fn exchange(
+        &mut self,
+        name: &str,
+        mut command: Split<char>,
+        task_lists: &mut Vec<TaskList>,
+    ) -> Result<CommandRequest, TaskCommandError> {
+        let (action, format) = name
+            .split_once('-')
+            .ok_or(TaskCommandError::UnknownCommand)?;
+        let format = Format::from_name(format).ok_or(TaskCommandError::UnknownCommand)?;
+        if action == "export" {
+            let lists = formats::pick_lists(
+                task_lists,
+                command.next().ok_or(TaskCommandError::MissingField)?,
+            )?;
+            let filename = command.next().ok_or(TaskCommandError::MissingField)?;
+            formats::write(filename, &format.export(&lists)?)?;
+            return Ok(CommandRequest::None);
+        }
+
+        let filename = command.next().ok_or(TaskCommandError::MissingField)?;
+        let text = formats::read(filename)?;
+        let imported = format.import(&text, &formats::default_list_name(filename))?;
+        if imported.lists.is_empty() {
+            return Err(TaskCommandError::NotFound);
+        }
+        self.checkpoint(task_lists);
+        formats::add_lists(task_lists, imported.lists);
+        self.reveal_selection(task_lists);
+        if !imported.summary.is_empty() {
+            self.import_window
+                .show(imported.summary.join("\n"), vec!["OK".to_string()]);
+        }
+        Ok(CommandRequest::SetActive)
     }
 
     fn save_list(&mut self, task_list: &TaskList) -> Result<CommandRequest, TaskCommandError> {
-        let mut file = match File::create(format!("{}.json", task_list.name)) {
-            Ok(f) => f,
-            Err(_) => return Err(TaskCommandError::InvalidFilePath),
-        };
-        let out = serde_json::to_vec(task_list).unwrap();
-        _ = file.write_all(&out).unwrap();
+        let filename = self.data_dir.join(format!("{}.json", task_list.name));
+        storage::save_list(&filename.to_string_lossy(), task_list)?;
         Ok(CommandRequest::None)
     }
 
-    fn mark_task(&mut self, task_lists: &mut Vec<TaskList>) {
-        if task_lists.is_empty() || task_lists[self.selected].tasks.is_empty() {
+    // moves the selection to the task with the given id, expanding its
+    // parents when it is a sub-task
+    fn goto_task(&mut self, task_lists: &mut [TaskList], id: u64) -> Result<(), TaskCommandError> {
+        let (list_index, path) = find_task(task_lists, id).ok_or(TaskCommandError::NotFound)?;
+        let list = &mut task_lists[list_index];
+
+        self.selected = list_index;
+        for depth in 1..path.len() {
+            if let Some(parent) = list.get_task_mut(&path[..depth]) {
+                parent.expanded = true;
+            }
+        }
+        list.selected = path;
+        Ok(())
+    }
+
+    // the filter that applies to this list
+    fn active_filter(&self, list: &TaskList) -> Option<&TaskFilter> {
+        self.filter
+            .as_ref()
+            .filter(|f| f.list.as_ref().is_none_or(|name| *name == list.name))
+    }
+
+    // moves the selection off a task that is gone, hidden by the filter or
+    // inside a collapsed task
+    fn reveal_selection(&mut self, task_lists: &mut [TaskList]) {
+        if let Some(list) = task_lists.get_mut(self.selected) {
+            let filter = self.active_filter(list).cloned();
+            let rows = list.visible_paths(filter.as_ref());
+            if !rows.contains(&list.selected) {
+                list.selected = rows.into_iter().next().unwrap_or_default();
+            }
+        }
+    }
+
+    // the highlighted task, if the current list shows any
+    fn selected_path(&self, list: &TaskList) -> Option<Vec<usize>> {
+        list.get_task(&list.selected)?;
+        list.visible_paths(self.active_filter(list))
+            .contains(&list.selected)
+            .then(|| list.selected.clone())
+    }
+
+    fn selected_task<'a>(&self, task_lists: &'a [TaskList]) -> Option<&'a Task> {
+        let list = task_lists.get(self.selected)?;
+        list.get_task(&self.selected_path(list)?)
+    }
+
+    fn selected_id(&self, task_lists: &[TaskList]) -> Result<u64, TaskCommandError> {
+        self.selected_task(task_lists)
+            .map(|task| task.id)
+            .ok_or(TaskCommandError::NotFound)
+    }
+
+    fn selected_task_mut<'a>(&self, task_lists: &'a mut [TaskList]) -> Option<&'a mut Task> {
+        let list = task_lists.get_mut(self.selected)?;
+        let path = self.selected_path(list)?;
+        list.get_task_mut(&path)
+    }
+
+    fn mark_task(&mut self, task_lists: &mut [TaskList]) {
+        let Ok(id) = self.selected_id(task_lists) else {
+            return;
+        };
+        let index = dependencies::task_index(task_lists);
+        if index[&id].status == TaskStatus::InProgress
+            && dependencies::is_blocked(&index, index[&id])
+        {
+            self.error = Some(TaskCommandError::Blocked);
             return;
         }
 
-        let list = &mut task_lists[self.selected];
-        let task = &mut list.tasks[list.selected];
-        if task.sub_tasks.len() > 0 && task.expanded && self.selected_sub_task != 0 {
-            task.sub_tasks[self.selected_sub_task - 1]
-                .status
-                .cycle_next();
-        } else {
-            task.status.cycle_next();
+        self.checkpoint(task_lists);
+        if let Some(list) = task_lists.get_mut(self.selected)
+            && let Some(path) = self.selected_path(list)
+            && let Some((siblings, index)) = list.siblings_mut(&path)
+        {
+            siblings[index].status.cycle_next();
+            complete_recurring(siblings, index);
         }
     }
 
-    fn try_delete_task(&mut self, task_lists: &mut Vec<TaskList>) {
-        if task_lists.is_empty() || task_lists[self.selected].tasks.is_empty() {
+    // applies a change to the selected task, if there is one
+    fn change_task(&mut self, task_lists: &mut [TaskList], change: impl FnOnce(&mut Task)) {
+        if self.selected_task(task_lists).is_none() {
+            return;
+        }
+        self.checkpoint(task_lists);
+        if let Some(task) = self.selected_task_mut(task_lists) {
+            change(task);
+        }
+    }
+
+    // to be called before every change to the lists
+    fn checkpoint(&mut self, task_lists: &[TaskList]) {
+        self.push_snapshot(task_lists.to_vec());
+    }
+
+    // snapshot of the lists from before a change that might not have happened,
+    // shared by the history and the journal
+    fn push_snapshot(&mut self, snapshot: Vec<TaskList>) {
+        let snapshot = Rc::new(snapshot);
+        self.journal.note(Rc::clone(&snapshot));
+        self.history.push(snapshot);
+    }
+
+    pub fn undo(&mut self, task_lists: &mut Vec<TaskList>) -> Result<(), TaskCommandError> {
+        self.journal.note(Rc::new(task_lists.to_vec()));
+        if !self.history.undo(task_lists) {
+            return Err(TaskCommandError::NothingToUndo);
+        }
+        self.restore_selection(task_lists);
+        Ok(())
+    }
+
+    pub fn redo(&mut self, task_lists: &mut Vec<TaskList>) -> Result<(), TaskCommandError> {
+        self.journal.note(Rc::new(task_lists.to_vec()));
+        if !self.history.redo(task_lists) {
+            return Err(TaskCommandError::NothingToRedo);
+        }
+        self.restore_selection(task_lists);
+        Ok(())
+    }
+
+    // keeps the selected list in range after the lists were swapped out
+    fn restore_selection(&mut self, task_lists: &mut [TaskList]) {
+        self.selected = self.selected.min(task_lists.len().saturating_sub(1));
+        self.reveal_selection(task_lists);
+    }
+
+    // true while one of the tab's popups takes the keyboard
+    pub fn popup_open(&self) -> bool {
+        self.new_task_window.status == PopupStatus::InUse
+            || self.delete_conf_window.status == PopupStatus::InUse
+            || self.new_tasklist_window.status == PopupStatus::InUse
+            || self.changes_window.status == PopupStatus::InUse
+            || self.import_window.status == PopupStatus::InUse
+    }
+
+    fn try_delete_task(&mut self, task_lists: &mut [TaskList]) {
+        if self.selected_task_mut(task_lists).is_none() {
             return;
         }
 
         self.delete_conf_window.show();
     }
 
-    fn delete_task(&mut self, task_lists: &mut Vec<TaskList>) {
-        if task_lists.is_empty() || task_lists[self.selected].tasks.is_empty() {
+    // removes the selected task along with its sub-tasks and selects the
+    // sibling taking its place, or the parent once no siblings are left
+    fn delete_task(&mut self, task_lists: &mut [TaskList]) {
+        if self.selected_task(task_lists).is_none() {
             return;
         }
+        self.checkpoint(task_lists);
 
-        let selected_list = &mut task_lists[self.selected];
-        let task = &mut selected_list.tasks[selected_list.selected];
-        if task.sub_tasks.len() > 0 && task.expanded && self.selected_sub_task != 0 {
-            task.sub_tasks.remove(self.selected_sub_task - 1);
-            if self.selected_sub_task > task.sub_tasks.len() {
-                self.selected_sub_task -= 1;
-            }
-        } else {
-            selected_list.tasks.remove(selected_list.selected);
-            if selected_list.selected == selected_list.tasks.len() {
-                selected_list.previous_task();
-            }
+        let Some(selected_list) = task_lists.get_mut(self.selected) else {
+            return;
+        };
+        let Some(mut path) = self.selected_path(selected_list) else {
+            return;
+        };
+        let Some((siblings, index)) = selected_list.siblings_mut(&path) else {
+            return;
+        };
+
+        siblings.remove(index);
+        if siblings.is_empty() {
+            path.pop();
+        } else if index == siblings.len() {
+            *path.last_mut().unwrap() -= 1;
         }
+        selected_list.selected = path;
+        self.reveal_selection(task_lists);
     }
 
-    fn edit_task(&mut self, task_lists: &mut Vec<TaskList>) {
-        if task_lists.is_empty() || task_lists[self.selected].tasks.is_empty() {
+    // indents or outdents the selected task, keeping it selected
+    fn move_task(
+        &mut self,
+        task_lists: &mut [TaskList],
+        operation: fn(&mut TaskList, &[usize]) -> Option<Vec<usize>>,
+    ) {
+        let snapshot = task_lists.to_vec();
+        let Some(selected_list) = task_lists.get_mut(self.selected) else {
             return;
+        };
+        if let Some(path) = self.selected_path(selected_list)
+            && let Some(new_path) = operation(selected_list, &path)
+        {
+            selected_list.selected = new_path;
+            self.push_snapshot(snapshot);
         }
+        self.reveal_selection(task_lists);
+    }
 
-        let selected_list = &task_lists[self.selected];
-        let task = &selected_list.tasks[selected_list.selected];
-
-        if task.sub_tasks.len() > 0 && task.expanded && self.selected_sub_task != 0 {
-            self.new_task_window
-                .edit_task(task.sub_tasks[self.selected_sub_task - 1].clone());
-        } else {
+    fn edit_task(&mut self, task_lists: &mut [TaskList]) {
+        if let Some(task) = self.selected_task(task_lists) {
             self.new_task_window.edit_task(task.clone());
         }
     }
@@ -480,25 +1645,33 @@ impl TaskListTab {
         self.new_task_window.new_task();
     }
 
+    fn new_child_task(&mut self, task_lists: &[TaskList]) {
+        if self.selected_task(task_lists).is_some() {
+            self.new_task_window.new_child_task();
+        }
+    }
+
     fn new_task_list(&mut self) {
         self.new_tasklist_window.show();
     }
 
-    fn next_tab(&mut self, task_lists: &mut Vec<TaskList>) {
+    fn next_tab(&mut self, task_lists: &mut [TaskList]) {
         if task_lists.is_empty() {
             return;
         }
         self.selected = (self.selected + 1) % task_lists.len();
+        self.reveal_selection(task_lists);
     }
 
-    fn previous_tab(&mut self, task_lists: &mut Vec<TaskList>) {
+    fn previous_tab(&mut self, task_lists: &mut [TaskList]) {
         if task_lists.is_empty() {
             return;
         }
         self.selected = (self.selected + task_lists.len() - 1) % task_lists.len();
+        self.reveal_selection(task_lists);
     }
 
-    pub fn render(&self, area: Rect, buf: &mut Buffer, task_lists: &Vec<TaskList>) {
+    pub fn render(&self, area: Rect, buf: &mut Buffer, task_lists: &[TaskList]) {
         let horiz =
             Layout::horizontal(vec![Constraint::Percentage(70), Constraint::Percentage(30)]);
         let [list_area, details_area] = horiz.areas(area);
@@ -513,13 +1686,23 @@ impl TaskListTab {
             self.delete_conf_window.render(area, buf);
         } else if self.new_tasklist_window.status == PopupStatus::InUse {
             self.new_tasklist_window.render(area, buf);
+        } else if self.changes_window.status == PopupStatus::InUse {
+            self.changes_window.render(area, buf);
+        } else if self.import_window.status == PopupStatus::InUse {
+            self.import_window.render(area, buf);
         }
     }
 
-    fn render_list(&self, area: Rect, buf: &mut Buffer, task_lists: &Vec<TaskList>) {
+    fn render_list(&self, area: Rect, buf: &mut Buffer, task_lists: &[TaskList]) {
+        let filter = task_lists
+            .get(self.selected)
+            .and_then(|list| self.active_filter(list));
         let tasks_border = Block::bordered()
             .border_style(THEME.task_border)
-            .title("Tasks")
+            .title(match filter {
+                Some(filter) => format!("Tasks (#{})", filter.tag),
+                None => "Tasks".to_string(),
+            })
             .title_style(THEME.task_title)
             .style(THEME.task)
             .border_type(BorderType::Rounded);
@@ -557,83 +1740,60 @@ impl TaskListTab {
         Span::styled("Date", THEME.task_title).render(date_area, buf);
         Span::styled("Duration", THEME.task_title).render(duration_area, buf);
 
-        let selected_list = &task_lists[self.selected];
-        for (index, task) in selected_list.tasks.iter().enumerate() {
+        let task_index = dependencies::task_index(task_lists);
+        let Some(selected_list) = task_lists.get(self.selected) else {
+            return;
+        };
+        let rows = selected_list.visible_paths(filter);
+        let sub_task_row = Layout::horizontal([
+            Constraint::Min(20),
+            Constraint::Length(8),
+            Constraint::Length(10),
+        ]);
+        for (row, path) in rows.iter().enumerate() {
             if !area.intersects(tasks_inner_area) {
                 break;
             }
-
-            let [mark_area, desc_area, date_area, duration_area] =
-                horizontal.areas(tasks_inner_area);
-            Span::styled(
-                format!("[{}] ", task.status.get_symbol()),
-                if index == selected_list.selected {
-                    THEME.task_selected
-                } else {
-                    THEME.task
-                },
-            )
-            .render(mark_area, buf);
-            Span::styled(
-                format!(" {} ", task.name),
-                if index == selected_list.selected && self.selected_sub_task == 0 {
-                    THEME.task_selected
-                } else {
-                    THEME.task
-                },
-            )
-            .render(desc_area, buf);
-            Span::from(format!(" {} ", disp_md(task.date))).render(date_area, buf);
-            Span::from(format!(" {} ", task.duration)).render(duration_area, buf);
-
-            tasks_inner_area = tasks_inner_area.offset(Offset { x: 0, y: 1 });
-
-            if task.expanded {
-                let horizontal = Layout::horizontal([
-                    Constraint::Length(5),
-                    Constraint::Length(3),
-                    Constraint::Min(20),
-                    Constraint::Length(8),
-                    Constraint::Length(10),
-                ]);
-
-                for (sub_index, sub_task) in task.sub_tasks.iter().enumerate() {
-                    let [tree_area, mark_area, desc_area, _date_area, _duration_area] =
-                        horizontal.areas(tasks_inner_area);
-                    let style = if index == selected_list.selected
-                        && self.selected_sub_task == sub_index + 1
-                    {
+            let Some(task) = selected_list.get_task(path) else {
+                continue;
+            };
+            let style = if *path == selected_list.selected {
+                THEME.task_selected
+            } else {
+                THEME.task
+            };
+            let title = title_spans(task, dependencies::is_blocked(&task_index, task));
+
+            if path.len() == 1 {
+                let [mark_area, desc_area, date_area, duration_area] =
+                    horizontal.areas(tasks_inner_area);
+                Span::styled(
+                    format!("[{}] ", task.status.get_symbol()),
+                    if selected_list.selected.first() == path.first() {
                         THEME.task_selected
                     } else {
                         THEME.task
-                    };
-                    Line::from(vec![
-                        Span::from(if sub_index == task.sub_tasks.len() - 1 {
-                            " └─"
-                        } else {
-                            " ├─"
-                        })
-                        .style(style),
-                        Span::from(if sub_index == task.sub_tasks.len() - 1 {
-                            "─"
-                        } else {
-                            "─"
-                        })
-                        .style(style),
-                    ])
-                    .render(tree_area, buf);
-                    Span::styled(format!("[{}]", sub_task.status.get_symbol()), style)
-                        .render(mark_area, buf);
-                    Span::from(format!(" {} ", sub_task.name))
-                        .style(style)
-                        .render(desc_area, buf);
-                    tasks_inner_area = tasks_inner_area.offset(Offset { x: 0, y: 1 });
-                }
+                    },
+                )
+                .render(mark_area, buf);
+                Line::from(title).style(style).render(desc_area, buf);
+                Span::from(format!(" {} ", disp_md(task.date))).render(date_area, buf);
+                Span::from(format!(" {} ", task.duration)).render(duration_area, buf);
+            } else {
+                let [desc_area, _date_area, _duration_area] = sub_task_row.areas(tasks_inner_area);
+                let mut spans = vec![
+                    Span::from(tree_prefix(&rows, row)),
+                    Span::from(format!("[{}]", task.status.get_symbol())),
+                ];
+                spans.extend(title);
+                Line::from(spans).style(style).render(desc_area, buf);
             }
+
+            tasks_inner_area = tasks_inner_area.offset(Offset { x: 0, y: 1 });
         }
     }
 
-    fn render_details(&self, area: Rect, buf: &mut Buffer, task_lists: &Vec<TaskList>) {
+    fn render_details(&self, area: Rect, buf: &mut Buffer, task_lists: &[TaskList]) {
         let border = Block::bordered()
             .border_style(THEME.task_border)
             .style(THEME.task)
@@ -644,28 +1804,149 @@ impl TaskListTab {
 
         border.render(area, buf);
 
-        let selected_list = &task_lists[self.selected];
-
-        if selected_list.tasks.is_empty() {
-            Span::from("No task selected").render(inner_area, buf);
-            return;
+        let task = match self.selected_task(task_lists) {
+            Some(task) => task,
+            None => {
+                Span::from("No task selected").render(inner_area, buf);
+                return;
+            }
+        };
+        let task_index = dependencies::task_index(task_lists);
+        let mut dependency_lines: Vec<Line> = Vec::new();
+        if !task.blocked_by.is_empty() {
+            dependency_lines.push(Line::styled("Blocked by:", THEME.task_title));
+            for id in &task.blocked_by {
+                dependency_lines.push(dependency_line(*id, task_index.get(id).copied()));
+            }
+        }
+        let dependents = dependencies::dependents(&task_index, task.id);
+        if !dependents.is_empty() {
+            dependency_lines.push(Line::styled("Blocks:", THEME.task_title));
+            for dependent in dependents {
+                dependency_lines.push(dependency_line(dependent.id, Some(dependent)));
+            }
         }
 
-        let task = &selected_list.tasks[selected_list.selected];
-        let vertical =
-            Layout::vertical([1, 1, task.name.len() as u16 / inner_area.width + 1, 1, 1]);
-        let [status, _name, desc, date, duration] = vertical.areas(inner_area);
+        let title_height = task.title.chars().count() as u16 / inner_area.width.max(1) + 1;
+        let vertical = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(title_height),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(dependency_lines.len() as u16),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ]);
+        let [
+            status,
+            priority,
+            id,
+            title,
+            date,
+            duration,
+            repeat,
+            tags,
+            blockers,
+            notes_label,
+            notes,
+        ] = vertical.areas(inner_area);
 
         Span::from(format!("Status: {}", task.status.get_name())).render(status, buf);
-
-        // renders in desc area right now because tasks do not have a
-        // seperation between name and desc right now
-        Paragraph::new(task.name.as_str())
+        Line::from(vec![
+            Span::from("Priority: "),
+            Span::styled(task.priority.get_name(), task.priority.get_style()),
+        ])
+        .render(priority, buf);
+        Span::from(format!("ID: {}", task.id)).render(id, buf);
+
+        Paragraph::new(task.title.as_str())
+            .style(THEME.task_selected)
             .wrap(Wrap { trim: false })
-            .render(desc, buf);
+            .render(title, buf);
 
         Span::from(format!("Date: {}", task.date)).render(date, buf);
         Span::from(format!("Duration: {}", task.duration)).render(duration, buf);
+
+        if let Some(recurrence) = &task.recurrence {
+            Span::from(format!("Repeat: {recurrence}")).render(repeat, buf);
+        }
+
+        let mut tag_spans = vec![Span::from("Tags: ")];
+        for tag in &task.tags {
+            tag_spans.push(Span::styled(format!(" #{tag} "), THEME.tag));
+            tag_spans.push(Span::from(" "));
+        }
+        Line::from(tag_spans).render(tags, buf);
+
+        Text::from(dependency_lines).render(blockers, buf);
+
+        if !task.notes.is_empty() {
+            Span::styled("Notes:", THEME.task_title).render(notes_label, buf);
+            Paragraph::new(task.notes.as_str())
+                .wrap(Wrap { trim: false })
+                .render(notes, buf);
+        }
+    }
+}
+
+/*
+    Tree lines drawn in front of a sub-task: a guide for every ancestor
+    below the top level that still has siblings further down, then the
+    branch leading to the task itself.
+*/
+fn tree_prefix(rows: &[Vec<usize>], row: usize) -> String {
+    let path = &rows[row];
+    let has_next_sibling = |depth: usize| {
+        rows[row + 1..].iter().any(|other| {
+            other.len() > depth && other[..depth] == path[..depth] && other[depth] != path[depth]
+        })
+    };
+
+    (1..path.len())
+        .map(
+            |depth| match (depth == path.len() - 1, has_next_sibling(depth)) {
+                (false, true) => " │   ",
+                (false, false) => "     ",
+                (true, true) => " ├── ",
+                (true, false) => " └── ",
+            },
+        )
+        .collect()
+}
+
+// priority marker, title and tag chips shown for a task in the list view
+fn title_spans(task: &Task, blocked: bool) -> Vec<Span<'_>> {
+    let mut spans = vec![Span::styled(
+        format!(" {}", task.priority.get_symbol()),
+        task.priority.get_style(),
+    )];
+    if blocked {
+        spans.push(Span::styled(" ⊘", THEME.blocked));
+    }
+    spans.push(Span::from(format!(" {} ", task.title)));
+    if task.recurrence.is_some() {
+        spans.push(Span::styled("↻ ", THEME.task_title));
+    }
+    for tag in &task.tags {
+        spans.push(Span::styled(format!(" #{tag} "), THEME.tag));
+        spans.push(Span::from(" "));
+    }
+    spans
+}
+
+// one entry of the blocked by / blocks sections in the details pane
+fn dependency_line(id: u64, task: Option<&Task>) -> Line<'_> {
+    match task {
+        Some(task) => Line::from(vec![
+            Span::from(format!("  [{}] ", task.status.get_symbol())),
+            Span::styled(format!("{id} "), THEME.task_title),
+            Span::from(task.title.as_str()),
+        ]),
+        None => Line::styled(format!("  {id} (missing)"), THEME.task_title),
     }
 }
 
@@ -675,45 +1956,178 @@ pub struct CalendarTab {
 }
 
 impl CalendarTab {
-    pub fn handle_input(&mut self, key: KeyCode) -> bool {
+    pub fn handle_input(&mut self, _key: KeyCode) -> bool {
         false
     }
 
-    pub fn render(&self, area: Rect, buf: &mut Buffer) {
+    pub fn render(&self, area: Rect, buf: &mut Buffer, task_lists: &[TaskList]) {
         let horizontal = Layout::horizontal([Constraint::Length(24), Constraint::Min(50)]);
         let [cal, weekly] = horizontal.areas(area);
 
+        let today = chrono::offset::Local::now().date_naive();
+        let (month_start, month_end) = Calendar::visible_range(today);
+        let busy_days: Vec<NaiveDate> = scheduled_tasks(task_lists, month_start, month_end)
+            .into_iter()
+            .map(|(date, _)| date)
+            .collect();
+
         let cal_block = Block::bordered()
             .border_style(THEME.task_border)
             .title_style(THEME.task_title)
             .border_type(BorderType::Rounded)
             .title("Monthly View");
-        self.cal
-            .render(cal_block.inner(cal).offset(Offset { x: 1, y: 0 }), buf);
+        self.cal.render(
+            cal_block.inner(cal).offset(Offset { x: 1, y: 0 }),
+            buf,
+            &busy_days,
+        );
         cal_block.render(cal, buf);
 
-        Block::bordered()
+        let weekly_block = Block::bordered()
             .title("Weekly View")
             .border_style(THEME.task_border)
             .title_style(THEME.task_title)
-            .border_type(BorderType::Rounded)
-            .render(weekly, buf);
+            .border_type(BorderType::Rounded);
+        let weekly_inner = weekly_block.inner(weekly);
+        weekly_block.render(weekly, buf);
+
+        let week_start = today.week(Weekday::Sun).first_day();
+        let week_end = today.week(Weekday::Sun).last_day();
+        let scheduled = scheduled_tasks(task_lists, week_start, week_end);
+        let mut lines: Vec<Line> = Vec::new();
+        for day in week_start.iter_days().take(7) {
+            lines.push(Line::styled(
+                format!("{} {}", day.format("%a"), disp_md(day)),
+                if day == today {
+                    THEME.calendar.today
+                } else {
+                    THEME.task_title
+                },
+            ));
+            for (_, task) in scheduled.iter().filter(|(date, _)| *date == day) {
+                lines.push(Line::from(vec![
+                    Span::from(format!("  [{}]", task.status.get_symbol())),
+                    Span::from(format!(" {} ", task.title)),
+                    Span::styled(
+                        if task.recurrence.is_some() { "↻" } else { "" },
+                        THEME.task_title,
+                    ),
+                ]));
+            }
+        }
+        Text::from(lines).render(weekly_inner, buf);
     }
 }
 
+// when changes are written to the data file without asking
+#[derive(Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Autosave {
+    Off,
+    // once no key was pressed for this many seconds
+    AfterIdle(u32),
+    OnChange,
+}
+
+impl std::fmt::Display for Autosave {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Autosave::Off => write!(f, "off"),
+            Autosave::AfterIdle(seconds) => write!(f, "after {seconds} sec idle"),
+            Autosave::OnChange => write!(f, "on every change"),
+        }
+    }
+}
+
+impl FromStr for Autosave {
+    type Err = TaskCommandError;
+
+    // "off", "change" or a number of idle seconds
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Autosave::Off),
+            "change" => Ok(Autosave::OnChange),
+            seconds => match seconds.parse::<u32>() {
+                Ok(0) => Err(TaskCommandError::InvalidOption),
+                Ok(seconds) => Ok(Autosave::AfterIdle(seconds)),
+                Err(_) => Err(TaskCommandError::InvalidOption),
+            },
+        }
+    }
+}
+
+// stored as the settings of the data file, missing ones keep their default
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
 pub struct Options {
     pub delete_on_completion: bool,
     pub error_display_time: u32,
+    #[serde(skip)]
     pub refresh_rate: u32,
+    pub history_depth: usize,
+    pub backup_count: usize,
+    // focus loss also saves unless this is off
+    pub autosave: Autosave,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            delete_on_completion: false,
+            error_display_time: 2,
+            refresh_rate: 60,
+            history_depth: 100,
+            backup_count: 5,
+            autosave: Autosave::AfterIdle(30),
+        }
+    }
 }
 
 pub struct OptionsTab {}
 
+fn parse_count(value: Option<&str>) -> Result<usize, TaskCommandError> {
+    value
+        .ok_or(TaskCommandError::MissingField)?
+        .parse::<usize>()
+        .or(Err(TaskCommandError::NotANumber))
+}
+
 impl OptionsTab {
-    pub fn handle_input(&mut self, key: KeyCode) -> bool {
+    pub fn handle_input(&mut self, _key: KeyCode) -> bool {
         false
     }
 
+    pub fn process_command(
+        &mut self,
+        mut command: Split<char>,
+        options: &mut Options,
+        history: &mut History,
+    ) -> Result<CommandRequest, TaskCommandError> {
+        match command.next() {
+            Some("autosave") => {
+                options.autosave = command
+                    .next()
+                    .ok_or(TaskCommandError::MissingField)?
+                    .parse()?;
+                // the settings are saved along with the tasks
+                history.changed();
+                Ok(CommandRequest::SetActive)
+            }
+            Some("history") => {
+                options.history_depth = parse_count(command.next())?;
+                history.set_depth(options.history_depth);
+                history.changed();
+                Ok(CommandRequest::SetActive)
+            }
+            Some("backups") => {
+                options.backup_count = parse_count(command.next())?;
+                history.changed();
+                Ok(CommandRequest::SetActive)
+            }
+            None => Ok(CommandRequest::SetActive),
+            Some(_) => Err(TaskCommandError::UnknownCommand),
+        }
+    }
+
     pub fn render(&self, area: Rect, buf: &mut Buffer, options: &Options) {
         let border = Block::bordered()
             .border_type(BorderType::Rounded)
@@ -731,6 +2145,9 @@ impl OptionsTab {
                 "Error message display time: {} sec",
                 options.error_display_time
             )),
+            Line::from(format!("Undo history depth: {}", options.history_depth)),
+            Line::from(format!("Backups kept: {}", options.backup_count)),
+            Line::from(format!("Autosave: {}", options.autosave)),
         ]))
         .style(THEME.task)
         .block(border)
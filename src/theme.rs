@@ -1,10 +1,12 @@
-use ratatui::style::{Color, Style};
+use ratatui::style::{Color, Modifier, Style};
 
 pub struct Theme {
     pub root: Style,
     pub root_tab_selected: Style,
     pub root_cursor: Style,
+    pub root_unsaved: Style,
     pub command_error: Style,
+    pub command_info: Style,
     pub popup: Style,
     pub popup_focused: Style,
     pub popup_selected: Style,
@@ -15,22 +17,36 @@ pub struct Theme {
     pub task_list_selected: Style,
     pub task_border: Style,
     pub task_title: Style,
+    pub tag: Style,
+    pub blocked: Style,
     pub key_bind: Style,
     pub key_desc: Style,
     pub calendar: CalendarStyle,
+    pub priority: PriorityStyle,
 }
 
 pub struct CalendarStyle {
     pub today: Style,
     pub this_month: Style,
     pub other_month: Style,
+    pub busy: Style,
+}
+
+pub struct PriorityStyle {
+    pub none: Style,
+    pub low: Style,
+    pub medium: Style,
+    pub high: Style,
+    pub critical: Style,
 }
 
 pub const THEME: Theme = Theme {
     root: Style::new().fg(WHITE).bg(DARKER_GRAY),
     root_cursor: Style::new().fg(DARKER_GRAY).bg(WHITE),
+    root_unsaved: Style::new().fg(ORANGE).bg(DARKER_GRAY),
     root_tab_selected: Style::new().fg(YELLOW).bg(DARK_GRAY),
     command_error: Style::new().fg(WHITE).bg(RED),
+    command_info: Style::new().fg(BLACK).bg(BLUE_4),
     popup: Style::new().fg(WHITE),
     popup_focused: Style::new().fg(WHITE).bg(DARK_GRAY),
     popup_selected: Style::new().fg(YELLOW).bg(DARK_GRAY),
@@ -41,22 +57,32 @@ pub const THEME: Theme = Theme {
     task_list_selected: Style::new().fg(DARKER_GRAY).bg(YELLOW),
     task_border: Style::new().fg(DARK_GRAY),
     task_title: Style::new().fg(GRAY),
+    tag: Style::new().fg(BLACK).bg(BLUE_4),
+    blocked: Style::new().fg(RED),
     key_bind: Style::new().fg(BLACK).bg(DARK_GRAY),
     key_desc: Style::new().fg(DARK_GRAY).bg(BLACK),
     calendar: CalendarStyle {
         today: Style::new().fg(BLUE_4),
         this_month: Style::new().fg(WHITE),
         other_month: Style::new().fg(GRAY),
+        busy: Style::new().fg(YELLOW).add_modifier(Modifier::UNDERLINED),
+    },
+    priority: PriorityStyle {
+        none: Style::new(),
+        low: Style::new().fg(BLUE_4),
+        medium: Style::new().fg(YELLOW),
+        high: Style::new().fg(ORANGE),
+        critical: Style::new().fg(RED).add_modifier(Modifier::BOLD),
     },
 };
 
 const WHITE: Color = Color::Rgb(238, 238, 238);
-const LIGHT_GRAY: Color = Color::Rgb(188, 188, 188);
 const GRAY: Color = Color::Rgb(128, 128, 128);
 const DARK_GRAY: Color = Color::Rgb(68, 68, 68);
 const DARKER_GRAY: Color = Color::Rgb(38, 38, 38);
 const BLACK: Color = Color::Rgb(8, 8, 8);
 const YELLOW: Color = Color::Rgb(240, 180, 30);
+const ORANGE: Color = Color::Rgb(230, 120, 40);
 const RED: Color = Color::Rgb(210, 60, 60);
 
 //from apollo color palette
@@ -1,5 +1,6 @@
 use crossterm::{
     ExecutableCommand,
+    event::{DisableFocusChange, EnableFocusChange},
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
 use ratatui::prelude::*;
@@ -10,11 +11,14 @@ pub type Tui = Terminal<CrosstermBackend<Stdout>>;
 pub fn init() -> io::Result<Tui> {
     enable_raw_mode()?;
     stdout().execute(EnterAlternateScreen)?;
+    // focus events let the app save when the terminal loses focus
+    stdout().execute(EnableFocusChange)?;
     Terminal::new(CrosstermBackend::new(stdout()))
 }
 
 pub fn restore() -> io::Result<()> {
     disable_raw_mode()?;
+    stdout().execute(DisableFocusChange)?;
     stdout().execute(LeaveAlternateScreen)?;
     Ok(())
 }
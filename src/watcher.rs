@@ -0,0 +1,76 @@
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    ffi::OsString,
+    fs,
+    path::Path,
+    sync::mpsc::{Receiver, channel},
+    time::Duration,
+};
+
+/*
+    Notices writes to the data file by other programs. Uses the native
+    watcher (inotify on Linux) and falls back to polling when that cannot be
+    set up, e.g. once the inotify watch limit is reached. The directory is
+    watched rather than the file since saves replace the file by renaming.
+*/
+pub struct FileWatcher {
+    _watcher: Box<dyn Watcher>,
+    events: Receiver<notify::Result<Event>>,
+    file_name: OsString,
+}
+
+impl FileWatcher {
+    // None when the file can be watched neither way
+    pub fn new(filename: &str) -> Option<FileWatcher> {
+        let path = Path::new(filename);
+        let file_name = path.file_name()?.to_owned();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        // on the first run the directory is only created by the first save,
+        // a directory that does not exist yet cannot be watched either way
+        fs::create_dir_all(dir).ok()?;
+
+        let (sender, events) = channel();
+        let native =
+            RecommendedWatcher::new(sender.clone(), Config::default()).and_then(|mut watcher| {
+                watcher.watch(dir, RecursiveMode::NonRecursive)?;
+                Ok(Box::new(watcher) as Box<dyn Watcher>)
+            });
+        let watcher = match native {
+            Ok(watcher) => watcher,
+            Err(_) => {
+                let config = Config::default().with_poll_interval(Duration::from_secs(1));
+                let mut watcher = PollWatcher::new(sender, config).ok()?;
+                watcher.watch(dir, RecursiveMode::NonRecursive).ok()?;
+                Box::new(watcher)
+            }
+        };
+
+        Some(FileWatcher {
+            _watcher: watcher,
+            events,
+            file_name,
+        })
+    }
+
+    // true when the file was written since the last call, never blocks
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        while let Ok(event) = self.events.try_recv() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name() == Some(self.file_name.as_os_str()))
+            {
+                changed = true;
+            }
+        }
+        changed
+    }
+}
@@ -1,5 +1,5 @@
 use crate::theme::THEME;
-use chrono::{Datelike, Weekday};
+use chrono::{Datelike, Days, NaiveDate, Weekday};
 use ratatui::{layout::Offset, prelude::*};
 
 pub struct TextEntry {
@@ -110,13 +110,194 @@ impl TextEntry {
     }
 }
 
-static DAYS: [&'static str; 7] = ["Su", "Mo", "Tu", "We", "Th", "Fr", "Sa"];
+/*
+    Multi-line counterpart to TextEntry. The cursor is tracked as a
+    (line, char) pair and long lines are wrapped when rendered.
+*/
+pub struct TextArea {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+}
+
+impl Default for TextArea {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextArea {
+    pub fn new() -> Self {
+        TextArea {
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+        }
+    }
+
+    pub fn set_text(&mut self, new_text: &str) {
+        self.lines = new_text.split('\n').map(String::from).collect();
+        self.row = self.lines.len() - 1;
+        self.col = self.line_len();
+    }
+
+    pub fn get_text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn take(&mut self) -> String {
+        let text = self.get_text();
+        self.clear();
+        text
+    }
+
+    pub fn clear(&mut self) {
+        self.lines = vec![String::new()];
+        self.row = 0;
+        self.col = 0;
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.row].chars().count()
+    }
+
+    fn byte_index(&self) -> usize {
+        self.lines[self.row]
+            .char_indices()
+            .map(|(i, _)| i)
+            .nth(self.col)
+            .unwrap_or(self.lines[self.row].len())
+    }
+
+    pub fn move_cursor_left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len();
+        }
+    }
+
+    pub fn move_cursor_right(&mut self) {
+        if self.col < self.line_len() {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn move_cursor_up(&mut self) {
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(self.line_len());
+        }
+    }
+
+    pub fn move_cursor_down(&mut self) {
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(self.line_len());
+        }
+    }
+
+    pub fn move_cursor_home(&mut self) {
+        self.col = 0;
+    }
+
+    pub fn move_cursor_end(&mut self) {
+        self.col = self.line_len();
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let index = self.byte_index();
+        self.lines[self.row].insert(index, c);
+        self.col += 1;
+    }
+
+    pub fn newline(&mut self) {
+        let index = self.byte_index();
+        let rest = self.lines[self.row].split_off(index);
+        self.lines.insert(self.row + 1, rest);
+        self.row += 1;
+        self.col = 0;
+    }
+
+    pub fn remove(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+            let index = self.byte_index();
+            self.lines[self.row].remove(index);
+        } else if self.row > 0 {
+            // backspace at the start of a line joins it onto the previous one
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len();
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    /*
+        Wraps every line into rows of at most width chars and scrolls so
+        the cursor row is always inside the area.
+    */
+    pub fn render(&self, area: Rect, buf: &mut Buffer, style: Style, cursor_style: Option<Style>) {
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+        let width = area.width as usize;
+
+        let mut rows: Vec<String> = Vec::new();
+        let mut cursor = (0, 0);
+        for (line_index, line) in self.lines.iter().enumerate() {
+            if line_index == self.row {
+                cursor = (rows.len() + self.col / width, self.col % width);
+            }
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                rows.push(String::new());
+            }
+            for chunk in chars.chunks(width) {
+                rows.push(chunk.iter().collect());
+            }
+            // a cursor sitting just past a full row starts the next one
+            if line_index == self.row && cursor.0 >= rows.len() {
+                rows.push(String::new());
+            }
+        }
+
+        let scroll = (cursor.0 + 1).saturating_sub(area.height as usize);
+        let lines: Vec<Line> = rows
+            .into_iter()
+            .skip(scroll)
+            .take(area.height as usize)
+            .map(Line::from)
+            .collect();
+        Text::from(lines).style(style).render(area, buf);
+
+        if let Some(cursor_style) = cursor_style {
+            buf[(
+                area.x + cursor.1 as u16,
+                area.y + (cursor.0 - scroll) as u16,
+            )]
+                .set_style(cursor_style);
+        }
+    }
+}
+
+static DAYS: [&str; 7] = ["Su", "Mo", "Tu", "We", "Th", "Fr", "Sa"];
 
 #[derive(Default)]
 pub struct Calendar {}
 
 impl Calendar {
-    pub fn render(&self, area: Rect, buf: &mut Buffer) {
+    // first and last day shown in the six week grid around date
+    pub fn visible_range(date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        let start = date.with_day(1).unwrap().week(Weekday::Sun).first_day();
+        (start, start + Days::new(6 * 7 - 1))
+    }
+
+    pub fn render(&self, area: Rect, buf: &mut Buffer, busy_days: &[NaiveDate]) {
         Line::from(
             DAYS.iter()
                 .map(|day| Span::from(format!("{} ", day)))
@@ -125,8 +306,7 @@ impl Calendar {
         .render(area, buf);
 
         let date = chrono::offset::Local::now().date_naive();
-        let first = date.with_day(1).unwrap();
-        let cal_start = first.week(Weekday::Sun).first_day();
+        let (cal_start, _) = Self::visible_range(date);
         let lines: Vec<Line> = cal_start
             .iter_weeks()
             .take(6)
@@ -140,9 +320,13 @@ impl Calendar {
                                 if day.month() == date.month() {
                                     if day == date {
                                         THEME.calendar.today
+                                    } else if busy_days.contains(&day) {
+                                        THEME.calendar.busy
                                     } else {
                                         THEME.calendar.this_month
                                     }
+                                } else if busy_days.contains(&day) {
+                                    THEME.calendar.busy.patch(THEME.calendar.other_month)
                                 } else {
                                     THEME.calendar.other_month
                                 },